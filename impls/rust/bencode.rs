@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read, Write};
+
+// The bencode values exchanged with nREPL clients. Byte strings are
+// decoded as UTF-8, replacing invalid sequences.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bencode {
+    Int(i64),
+    Str(String),
+    List(Vec<Bencode>),
+    Dict(BTreeMap<String, Bencode>),
+}
+
+use self::Bencode::{Dict, Int, List, Str};
+
+// The longest byte string a peer may send, so that a bogus length can't
+// make us allocate without bound
+pub const MAX_STRING_LEN: usize = 16 * 1024 * 1024;
+
+// The most digits an integer or a string length may have, and how deeply
+// lists and dictionaries may nest, so that a bogus message can't make us
+// read or recurse without bound
+pub const MAX_DIGITS: usize = 20;
+pub const MAX_DEPTH: usize = 64;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// The digits up to end, of which there may be at most max
+fn read_until(r: &mut dyn BufRead, end: u8, max: usize) -> io::Result<String> {
+    let mut buf = Vec::new();
+    Read::take(&mut *r, max as u64 + 1).read_until(end, &mut buf)?;
+    if buf.last() != Some(&end) {
+        return Err(invalid(if buf.len() > max {
+            "number too long"
+        } else {
+            "unexpected end of message"
+        }));
+    }
+    buf.pop();
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn read_byte(r: &mut dyn BufRead) -> io::Result<Option<u8>> {
+    let b = match r.fill_buf()? {
+        [] => return Ok(None),
+        buf => buf[0],
+    };
+    r.consume(1);
+    Ok(Some(b))
+}
+
+// Read one value, None at the end of the stream
+pub fn read(r: &mut dyn BufRead) -> io::Result<Option<Bencode>> {
+    let b = match read_byte(r)? {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+    read_value(r, b, 0).map(Some)
+}
+
+// Read the value starting with b, nested in depth lists and dictionaries
+fn read_value(r: &mut dyn BufRead, b: u8, depth: usize) -> io::Result<Bencode> {
+    if depth >= MAX_DEPTH && (b == b'l' || b == b'd') {
+        return Err(invalid("nesting too deep"));
+    }
+    match b {
+        b'i' => read_until(r, b'e', MAX_DIGITS)?
+            .parse()
+            .map(Int)
+            .map_err(|_| invalid("bad integer")),
+        b'l' => {
+            let mut items = Vec::new();
+            loop {
+                match read_byte(r)? {
+                    Some(b'e') => return Ok(List(items)),
+                    Some(b) => items.push(read_value(r, b, depth + 1)?),
+                    None => return Err(invalid("unexpected end of message")),
+                }
+            }
+        }
+        b'd' => {
+            let mut dict = BTreeMap::new();
+            loop {
+                let key = match read_byte(r)? {
+                    Some(b'e') => return Ok(Dict(dict)),
+                    Some(b) => match read_value(r, b, depth + 1)? {
+                        Str(k) => k,
+                        _ => return Err(invalid("dictionary key is not a string")),
+                    },
+                    None => return Err(invalid("unexpected end of message")),
+                };
+                let val = match read_byte(r)? {
+                    Some(b) => read_value(r, b, depth + 1)?,
+                    None => return Err(invalid("unexpected end of message")),
+                };
+                dict.insert(key, val);
+            }
+        }
+        b'0'..=b'9' => {
+            let len: usize = format!("{}{}", b as char, read_until(r, b':', MAX_DIGITS - 1)?)
+                .parse()
+                .map_err(|_| invalid("bad string length"))?;
+            if len > MAX_STRING_LEN {
+                return Err(invalid("string too long"));
+            }
+            let mut buf = Vec::new();
+            Read::take(&mut *r, len as u64).read_to_end(&mut buf)?;
+            if buf.len() < len {
+                return Err(invalid("unexpected end of message"));
+            }
+            Ok(Str(String::from_utf8_lossy(&buf).into_owned()))
+        }
+        _ => Err(invalid("unexpected byte")),
+    }
+}
+
+pub fn encode(v: &Bencode, out: &mut Vec<u8>) {
+    match v {
+        Int(i) => out.extend(format!("i{}e", i).bytes()),
+        Str(s) => {
+            out.extend(format!("{}:", s.len()).bytes());
+            out.extend(s.bytes());
+        }
+        List(items) => {
+            out.push(b'l');
+            for item in items {
+                encode(item, out);
+            }
+            out.push(b'e');
+        }
+        Dict(dict) => {
+            out.push(b'd');
+            for (k, v) in dict {
+                encode(&Str(k.clone()), out);
+                encode(v, out);
+            }
+            out.push(b'e');
+        }
+    }
+}
+
+pub fn write(w: &mut dyn Write, v: &Bencode) -> io::Result<()> {
+    let mut out = Vec::new();
+    encode(v, &mut out);
+    w.write_all(&out)?;
+    w.flush()
+}
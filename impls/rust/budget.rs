@@ -0,0 +1,119 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::types::MalErr::{ErrInterrupted, ErrLimit};
+use crate::types::MalVal::{Hash, List, Set, Vector};
+use crate::types::{Limit, MalErr, MalVal};
+
+// Bounds on a single evaluation. A `None` field is unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub alloc: Option<u64>,
+}
+
+struct Scope {
+    limits: Limits,
+    steps: u64,
+    alloc: u64,
+    start: Instant,
+    exceeded: Option<Limit>,
+}
+
+impl Scope {
+    fn check(&mut self) -> Result<(), MalErr> {
+        if self.exceeded.is_none() {
+            let l = self.limits;
+            self.exceeded = match (l.steps, l.timeout, l.alloc) {
+                (Some(n), _, _) if self.steps > n => Some(Limit::Steps(n)),
+                (_, _, Some(n)) if self.alloc > n => Some(Limit::Alloc(n)),
+                (_, Some(d), _) if self.start.elapsed() > d => Some(Limit::Timeout(d)),
+                _ => None,
+            };
+        }
+        match self.exceeded {
+            Some(l) => Err(ErrLimit(l)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Active budgets, innermost last. Nested scopes are all charged, so an
+// inner `with-limits` can never escape the bounds of an outer one.
+thread_local! {
+    static SCOPES: RefCell<Vec<Scope>> = const { RefCell::new(Vec::new()) };
+}
+
+// Set asynchronously (e.g. from a SIGINT handler) and polled by the eval loop
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        SCOPES.with(|s| s.borrow_mut().pop());
+    }
+}
+
+fn charge(steps: u64, alloc: u64) -> Result<(), MalErr> {
+    SCOPES.with(|s| {
+        for scope in s.borrow_mut().iter_mut() {
+            scope.steps += steps;
+            scope.alloc += alloc;
+            scope.check()?;
+        }
+        Ok(())
+    })
+}
+
+// Run `f` under `limits`, on top of any budget that is already active
+pub fn with_limits<T, F: FnOnce() -> T>(limits: Limits, f: F) -> T {
+    SCOPES.with(|s| {
+        s.borrow_mut().push(Scope {
+            limits,
+            steps: 0,
+            alloc: 0,
+            start: Instant::now(),
+            exceeded: None,
+        })
+    });
+    let _guard = ScopeGuard;
+    f()
+}
+
+// Called once per iteration of the eval loop
+pub fn tick() -> Result<(), MalErr> {
+    if INTERRUPTED.swap(false, Ordering::Relaxed) {
+        return Err(ErrInterrupted);
+    }
+    charge(1, 0)
+}
+
+// Ask the running evaluation to unwind with an "interrupted" error. Safe
+// to call from any thread or from a signal handler.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+// Forget an interrupt that arrived while nothing was being evaluated
+pub fn clear_interrupt() {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+}
+
+// Account for the elements of a freshly built collection
+pub fn alloc(mv: &MalVal) -> Result<(), MalErr> {
+    match mv {
+        List(v, _) | Vector(v, _) => charge(0, v.len() as u64),
+        Hash(hm, _) => charge(0, hm.len() as u64),
+        Set(s, _) => charge(0, s.len() as u64),
+        _ => Ok(()),
+    }
+}
+
+// True while any active budget is exhausted. Limit errors raised inside
+// such a scope must not be swallowed by `try*`.
+pub fn exceeded() -> bool {
+    SCOPES.with(|s| s.borrow().iter().any(|scope| scope.exceeded.is_some()))
+}
@@ -1,27 +1,30 @@
-use std::fs::File;
-use std::io::Read;
 use std::rc::Rc;
 use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use fnv::FnvHashSet;
+use regex::{Captures, Match, NoExpand, Regex};
+
 extern crate rustyline;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-use crate::printer::pr_seq;
-use crate::reader::read_str;
-use crate::types::MalErr::ErrMalVal;
-use crate::types::MalVal::{Atom, Bool, Func, Hash, Int, List, MalFunc, Nil, Str, Sym, Vector};
-use crate::types::{MalArgs, MalRet, MalVal, _assoc, _dissoc, atom, error, func, hash_map};
-
-macro_rules! fn_t_int_int {
-    ($ret:ident, $fn:expr) => {{
-        |a: MalArgs| match (a[0].clone(), a[1].clone()) {
-            (Int(a0), Int(a1)) => Ok($ret($fn(a0, a1))),
-            _ => error("expecting (int,int) args"),
-        }
-    }};
-}
+use crate::env::{env_keys, env_sets, Env};
+use crate::files;
+use crate::json;
+use crate::printer::{pr_seq, write_line};
+use crate::process;
+use crate::reader::{read_edn, read_str};
+use crate::sandbox::{check_clock, check_stdin};
+use crate::strings;
+use crate::types::MalErr::{ErrMalVal, ErrString};
+use crate::types::MalVal::{
+    Atom, Bool, Char, Closure, Func, Hash, Int, List, MalFunc, Nil, Set, Str, Sym, Tagged, Vector,
+};
+use crate::types::{
+    _assoc, _dissoc, arg, atom, builtin, check_arity, compile_regex, error, func, hash_map,
+    re_pattern, set, typed_fn, MalArgs, MalErr, MalRet, MalVal, NativeClosure,
+};
 
 macro_rules! fn_is_type {
   ($($ps:pat),*) => {{
@@ -35,59 +38,46 @@ macro_rules! fn_is_type {
   }};
 }
 
-macro_rules! fn_str {
-    ($fn:expr) => {{
-        |a: MalArgs| match a[0].clone() {
-            Str(a0) => $fn(a0),
-            _ => error("expecting (str) arg"),
-        }
-    }};
-}
-
-fn symbol(a: MalArgs) -> MalRet {
-    match a[0] {
-        Str(ref s) => Ok(Sym(s.to_string())),
-        _ => error("illegal symbol call"),
-    }
-}
-
-fn readline(a: MalArgs) -> MalRet {
+fn readline(p: String) -> MalRet {
     lazy_static! {
         static ref RL: Mutex<Editor<()>> = Mutex::new(Editor::<()>::new());
     }
+    check_stdin("readline")?;
     //let mut rl = Editor::<()>::new();
 
-    match a[0] {
-        Str(ref p) => {
-            //match rl.readline(p) {
-            match RL.lock().unwrap().readline(p) {
-                Ok(mut line) => {
-                    // Remove any trailing \n or \r\n
-                    if line.ends_with('\n') {
-                        line.pop();
-                        if line.ends_with('\r') {
-                            line.pop();
-                        }
-                    }
-                    Ok(Str(line))
+    //match rl.readline(&p) {
+    match RL.lock().unwrap().readline(&p) {
+        Ok(mut line) => {
+            // Remove any trailing \n or \r\n
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
                 }
-                Err(ReadlineError::Eof) => Ok(Nil),
-                Err(e) => error(&format!("{:?}", e)),
             }
+            Ok(Str(line))
         }
-        _ => error("readline: prompt is not Str"),
+        Err(ReadlineError::Eof) => Ok(Nil),
+        Err(e) => error(&format!("{:?}", e)),
     }
 }
 
-fn slurp(f: String) -> MalRet {
-    let mut s = String::new();
-    match File::open(f).and_then(|mut f| f.read_to_string(&mut s)) {
-        Ok(_) => Ok(Str(s)),
-        Err(e) => error(&e.to_string()),
+fn spit(a: MalArgs) -> MalRet {
+    let path: String = arg("spit", &a, 0)?;
+    files::spit(&path, &a[1], flag("spit", a.get(2), "append")?)
+}
+
+fn open(a: MalArgs) -> MalRet {
+    let path: String = arg("open", &a, 0)?;
+    match a.get(1) {
+        None => files::open(path, "read"),
+        Some(Str(m)) if a[1].keyword_q() => files::open(path, &m[2..]),
+        Some(_) => error("open: mode must be :read, :write or :append"),
     }
 }
 
 fn time_ms(_a: MalArgs) -> MalRet {
+    check_clock("time-ms")?;
     let ms_e = match SystemTime::now().duration_since(UNIX_EPOCH) {
         Ok(d) => d,
         Err(e) => return error(&format!("{:?}", e)),
@@ -104,6 +94,9 @@ fn get(a: MalArgs) -> MalRet {
             Some(mv) => Ok(mv.clone()),
             None => Ok(Nil),
         },
+        (Tagged(ref t, _), Str(ref s)) if s == "\u{29e}tag" => Ok(Sym(t.to_string())),
+        (Tagged(_, ref v), Str(ref s)) if s == "\u{29e}form" => Ok((**v).clone()),
+        (Tagged(..), _) => Ok(Nil),
         _ => error("illegal get args"),
     }
 }
@@ -125,6 +118,7 @@ fn dissoc(a: MalArgs) -> MalRet {
 fn contains_q(a: MalArgs) -> MalRet {
     match (a[0].clone(), a[1].clone()) {
         (Hash(ref hm, _), Str(ref s)) => Ok(Bool(hm.contains_key(s))),
+        (Set(ref s, _), ref x) => Ok(Bool(s.contains(x))),
         _ => error("illegal get args"),
     }
 }
@@ -150,15 +144,10 @@ fn vec(a: MalArgs) -> MalRet {
     }
 }
 
-fn cons(a: MalArgs) -> MalRet {
-    match a[1].clone() {
-        List(v, _) | Vector(v, _) => {
-            let mut new_v = vec![a[0].clone()];
-            new_v.extend_from_slice(&v);
-            Ok(list!(new_v.to_vec()))
-        }
-        _ => error("cons expects seq as second arg"),
-    }
+fn cons(x: MalVal, v: Rc<Vec<MalVal>>) -> MalVal {
+    let mut new_v = vec![x];
+    new_v.extend_from_slice(&v);
+    list!(new_v)
 }
 
 fn concat(a: MalArgs) -> MalRet {
@@ -172,15 +161,26 @@ fn concat(a: MalArgs) -> MalRet {
     Ok(list!(new_v.to_vec()))
 }
 
-fn nth(a: MalArgs) -> MalRet {
-    match (a[0].clone(), a[1].clone()) {
-        (List(seq, _), Int(idx)) | (Vector(seq, _), Int(idx)) => {
-            if seq.len() <= idx as usize {
-                return error("nth: index out of range");
-            }
-            Ok(seq[idx as usize].clone())
-        }
-        _ => error("invalid args to nth"),
+fn nth(seq: Rc<Vec<MalVal>>, idx: i64) -> MalRet {
+    if idx < 0 || seq.len() <= idx as usize {
+        return error("nth: index out of range");
+    }
+    Ok(seq[idx as usize].clone())
+}
+
+// The result of the arithmetic function name, None when it overflowed
+fn checked(name: &str, n: Option<i64>) -> MalRet {
+    match n {
+        Some(n) => Ok(Int(n)),
+        None => Err(ErrString(format!("{}: integer overflow", name))),
+    }
+}
+
+fn divide(i: i64, j: i64) -> MalRet {
+    match i.checked_div(j) {
+        Some(q) => Ok(Int(q)),
+        None if j == 0 => error("/: division by zero"),
+        None => error("/: integer overflow"),
     }
 }
 
@@ -208,6 +208,9 @@ fn rest(a: MalArgs) -> MalRet {
 }
 
 fn apply(a: MalArgs) -> MalRet {
+    if a.len() < 2 {
+        return error("apply: expected a function and a sequence of arguments");
+    }
     match a[a.len() - 1] {
         List(ref v, _) | Vector(ref v, _) => {
             let f = &a[0];
@@ -232,6 +235,7 @@ fn map(a: MalArgs) -> MalRet {
     }
 }
 
+#[allow(clippy::mutable_key_type)]
 fn conj(a: MalArgs) -> MalRet {
     match a[0] {
         List(ref v, _) => {
@@ -243,6 +247,11 @@ fn conj(a: MalArgs) -> MalRet {
             Ok(list!([&sl[..], v].concat()))
         }
         Vector(ref v, _) => Ok(vector!([v, &a[1..]].concat())),
+        Set(ref s, _) => {
+            let mut s = (**s).clone();
+            s.extend(a[1..].iter().cloned());
+            Ok(Set(Rc::new(s), Rc::new(Nil)))
+        }
         _ => error("conj: called with non-seq"),
     }
 }
@@ -251,6 +260,8 @@ fn seq(a: MalArgs) -> MalRet {
     match a[0] {
         List(ref v, _) | Vector(ref v, _) if v.len() == 0 => Ok(Nil),
         List(ref v, _) | Vector(ref v, _) => Ok(list!(v.to_vec())),
+        Set(ref s, _) if s.is_empty() => Ok(Nil),
+        Set(ref s, _) => Ok(list!(s.iter().cloned().collect())),
         Str(ref s) if s.len() == 0 => Ok(Nil),
         Str(ref s) if !a[0].keyword_q() => {
             Ok(list!(s.chars().map(|c| { Str(c.to_string()) }).collect()))
@@ -260,92 +271,983 @@ fn seq(a: MalArgs) -> MalRet {
     }
 }
 
-pub fn ns() -> Vec<(&'static str, MalVal)> {
+fn to_set(a: MalArgs) -> MalRet {
+    match a[0] {
+        List(ref v, _) | Vector(ref v, _) => Ok(set(v.to_vec())),
+        Set(ref s, _) => Ok(Set(s.clone(), Rc::new(Nil))),
+        Nil => Ok(set(vec![])),
+        _ => error("set: called with non-seq"),
+    }
+}
+
+// The sets passed to name. Atoms hash the same whatever they hold, so the
+// interior mutability of mal values can't break a set.
+#[allow(clippy::mutable_key_type)]
+fn sets(name: &str, a: &[MalVal]) -> Result<Vec<Rc<FnvHashSet<MalVal>>>, MalErr> {
+    a.iter()
+        .enumerate()
+        .map(|(i, mv)| match mv {
+            Set(s, _) => Ok(s.clone()),
+            _ => Err(ErrString(format!(
+                "expected set as argument {} of {}",
+                i + 1,
+                name
+            ))),
+        })
+        .collect()
+}
+
+#[allow(clippy::mutable_key_type)]
+fn disj(a: MalArgs) -> MalRet {
+    let mut s = (*sets("disj", &a[..1])?[0]).clone();
+    for x in a[1..].iter() {
+        s.remove(x);
+    }
+    Ok(Set(Rc::new(s), Rc::new(Nil)))
+}
+
+#[allow(clippy::mutable_key_type)]
+fn union(a: MalArgs) -> MalRet {
+    let mut res = FnvHashSet::default();
+    for s in sets("union", &a)? {
+        res.extend(s.iter().cloned());
+    }
+    Ok(Set(Rc::new(res), Rc::new(Nil)))
+}
+
+#[allow(clippy::mutable_key_type)]
+fn intersection(a: MalArgs) -> MalRet {
+    let all = sets("intersection", &a)?;
+    let res = all[0]
+        .iter()
+        .filter(|x| all[1..].iter().all(|s| s.contains(*x)))
+        .cloned()
+        .collect();
+    Ok(Set(Rc::new(res), Rc::new(Nil)))
+}
+
+#[allow(clippy::mutable_key_type)]
+fn difference(a: MalArgs) -> MalRet {
+    let all = sets("difference", &a)?;
+    let res = all[0]
+        .iter()
+        .filter(|x| !all[1..].iter().any(|s| s.contains(*x)))
+        .cloned()
+        .collect();
+    Ok(Set(Rc::new(res), Rc::new(Nil)))
+}
+
+// The documentation of a function: its call signature followed by its
+// docstring, indented. User functions keep their :name and :doc in their
+// metadata, builtins carry theirs in the closure that ns() makes, so that
+// their metadata stays nil. `name` is used for functions that don't know
+// theirs.
+pub fn describe(f: &MalVal, name: &str) -> Result<String, MalErr> {
+    let (name, params, doc) = match f {
+        Closure(c, _) => match c.doc {
+            Some((params, doc)) => (
+                c.name.to_string(),
+                Some(params.to_string()),
+                Some(doc.to_string()),
+            ),
+            None => (c.name.to_string(), None, None),
+        },
+        Func(..) => (name.to_string(), None, None),
+        MalFunc { params, meta, .. } => {
+            let field = |key: &str| match **meta {
+                Hash(ref hm, _) => match hm.get(&format!("\u{29e}{}", key)) {
+                    Some(Str(s)) => Some(s.to_string()),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let name = field("name").unwrap_or_else(|| name.to_string());
+            (name, Some(params.pr_str(true)), field("doc"))
+        }
+        _ => return Err(ErrString("doc: not a function".to_string())),
+    };
+    let mut out = match params {
+        Some(ref p) if p.len() > 2 => format!("({} {})", name, &p[1..p.len() - 1]),
+        Some(_) => format!("({})", name),
+        None => name,
+    };
+    for line in doc.iter().flat_map(|d| d.lines()) {
+        out.push_str("\n  ");
+        out.push_str(line);
+    }
+    Ok(out)
+}
+
+// The number of required arguments in a params string like "(f & args)",
+// and whether more are allowed
+pub fn arity(params: &str) -> (usize, bool) {
+    let names: Vec<&str> = params[1..params.len() - 1].split_whitespace().collect();
+    match names.iter().position(|n| *n == "&") {
+        Some(i) => (i, true),
+        None => (names.len(), false),
+    }
+}
+
+// Whether the flag key is set in the map of options that may follow the
+// arguments of name
+fn flag(name: &str, opts: Option<&MalVal>, key: &str) -> Result<bool, MalErr> {
+    match opts {
+        None | Some(Nil) => Ok(false),
+        Some(Hash(hm, _)) => match hm.get(&format!("\u{29e}{}", key)) {
+            None | Some(Nil) | Some(Bool(false)) => Ok(false),
+            _ => Ok(true),
+        },
+        Some(_) => Err(ErrString(format!("{}: options must be a hash-map", name))),
+    }
+}
+
+fn tagged_literal(a: MalArgs) -> MalRet {
+    match a[0] {
+        Sym(ref t) => Ok(Tagged(t.to_string(), Rc::new(a[1].clone()))),
+        _ => error("tagged-literal: tag must be a symbol"),
+    }
+}
+
+fn json_encode(a: MalArgs) -> MalRet {
+    json::encode(&a[0], flag("json-encode", a.get(1), "pretty")?)
+}
+
+fn json_decode(a: MalArgs) -> MalRet {
+    match a[0] {
+        Str(ref s) if !a[0].keyword_q() => {
+            json::decode(s, flag("json-decode", a.get(1), "keywordize")?)
+        }
+        _ => error("json-decode: expecting a string"),
+    }
+}
+
+// A match of a regex: the matched text when the regex has no groups,
+// otherwise a vector of it followed by the groups, nil for those that
+// didn't take part in the match
+fn re_groups(caps: &Captures) -> MalVal {
+    let text = |m: Option<Match>| m.map_or(Nil, |m| Str(m.as_str().to_string()));
+    match caps.len() {
+        1 => text(caps.get(0)),
+        _ => vector!(caps.iter().map(text).collect()),
+    }
+}
+
+fn regex_pattern(a: MalArgs) -> MalRet {
+    match a[0] {
+        MalVal::Regex(_) => Ok(a[0].clone()),
+        Str(ref s) if !a[0].keyword_q() => re_pattern(s),
+        _ => error("re-pattern: expecting a string"),
+    }
+}
+
+fn re_find(re: Regex, s: String) -> MalVal {
+    re.captures(&s).map_or(Nil, |caps| re_groups(&caps))
+}
+
+fn re_matches(re: Regex, s: String) -> MalRet {
+    let whole = compile_regex(&format!(r"\A(?:{})\z", re.as_str()))?;
+    Ok(re_find(whole, s))
+}
+
+fn re_seq(re: Regex, s: String) -> MalVal {
+    let matches: Vec<MalVal> = re.captures_iter(&s).map(|c| re_groups(&c)).collect();
+    match matches.len() {
+        0 => Nil,
+        _ => list!(matches),
+    }
+}
+
+// Replaces the first limit matches of m in s, all of them when limit is 0.
+// m is a string or a regex, and r a string, where $1 refers to a group of
+// a regex, or a function called with each match that returns its
+// replacement.
+fn replace(name: &str, s: &str, m: &MalVal, r: &MalVal, limit: usize) -> MalRet {
+    let (re, literal) = match m {
+        MalVal::Regex(re) => (re.clone(), false),
+        Str(p) if !m.keyword_q() => (compile_regex(&regex::escape(p))?, true),
+        _ => return error(&format!("{}: match must be a string or a regex", name)),
+    };
+    match r {
+        Str(r) if literal => Ok(Str(re.replacen(s, limit, NoExpand(r)).into_owned())),
+        Str(r) => Ok(Str(re.replacen(s, limit, r.as_str()).into_owned())),
+        Func(..) | Closure(..) | MalFunc { .. } => {
+            let mut res = String::new();
+            let mut last = 0;
+            let n = if limit == 0 { usize::MAX } else { limit };
+            for caps in re.captures_iter(s).take(n) {
+                let (start, end) = caps.get(0).map_or((0, 0), |m| (m.start(), m.end()));
+                res.push_str(&s[last..start]);
+                res.push_str(&r.apply(vec![re_groups(&caps)])?.pr_str(false));
+                last = end;
+            }
+            res.push_str(&s[last..]);
+            Ok(Str(res))
+        }
+        _ => error(&format!(
+            "{}: replacement must be a string or a function",
+            name
+        )),
+    }
+}
+
+fn pprint(a: MalArgs) -> MalRet {
+    let width = match a.get(1) {
+        None => 80,
+        Some(Int(w)) if *w > 0 => *w as usize,
+        _ => return error("pprint: width must be a positive integer"),
+    };
+    write_line(&a[0].pprint(width));
+    Ok(Nil)
+}
+
+fn doc(a: MalArgs) -> MalRet {
+    write_line(&describe(&a[0], "fn*")?);
+    Ok(Nil)
+}
+
+// The names defined in env that contain the string a[0], builtins and
+// user definitions alike
+fn apropos(env: &Env, a: MalArgs) -> MalRet {
+    match a[0] {
+        Str(ref s) if !a[0].keyword_q() => {
+            let mut names: Vec<String> = env_keys(env)
+                .into_iter()
+                .filter(|name| name.contains(s.as_str()))
+                .collect();
+            names.sort();
+            Ok(list!(names.into_iter().map(Sym).collect()))
+        }
+        _ => error("apropos: expecting a string"),
+    }
+}
+
+// name, parameters, docstring, implementation
+pub fn natives() -> Vec<(&'static str, &'static str, &'static str, MalVal)> {
     vec![
-        ("=", func(|a| Ok(Bool(a[0] == a[1])))),
-        ("throw", func(|a| Err(ErrMalVal(a[0].clone())))),
-        ("nil?", func(fn_is_type!(Nil))),
-        ("true?", func(fn_is_type!(Bool(true)))),
-        ("false?", func(fn_is_type!(Bool(false)))),
-        ("symbol", func(symbol)),
-        ("symbol?", func(fn_is_type!(Sym(_)))),
+        (
+            "=",
+            "(a b)",
+            "True if a and b are equal.",
+            func(|a| Ok(Bool(a[0] == a[1]))),
+        ),
+        (
+            "throw",
+            "(x)",
+            "Throws x as an exception, to be caught by try*/catch*.",
+            func(|a| Err(ErrMalVal(a[0].clone()))),
+        ),
+        ("nil?", "(x)", "True if x is nil.", func(fn_is_type!(Nil))),
+        (
+            "true?",
+            "(x)",
+            "True if x is true.",
+            func(fn_is_type!(Bool(true))),
+        ),
+        (
+            "false?",
+            "(x)",
+            "True if x is false.",
+            func(fn_is_type!(Bool(false))),
+        ),
+        (
+            "symbol",
+            "(name)",
+            "Returns the symbol with the given name.",
+            typed_fn("symbol", |s: String| Sym(s)),
+        ),
+        (
+            "symbol?",
+            "(x)",
+            "True if x is a symbol.",
+            func(fn_is_type!(Sym(_))),
+        ),
         (
             "string?",
+            "(x)",
+            "True if x is a string.",
             func(fn_is_type!(Str(ref s) if !s.starts_with("\u{29e}"))),
         ),
-        ("keyword", func(|a| a[0].keyword())),
+        (
+            "keyword",
+            "(name)",
+            "Returns the keyword with the given name.",
+            func(|a| a[0].keyword()),
+        ),
         (
             "keyword?",
+            "(x)",
+            "True if x is a keyword.",
             func(fn_is_type!(Str(ref s) if s.starts_with("\u{29e}"))),
         ),
-        ("number?", func(fn_is_type!(Int(_)))),
+        (
+            "number?",
+            "(x)",
+            "True if x is a number.",
+            func(fn_is_type!(Int(_))),
+        ),
         (
             "fn?",
-            func(fn_is_type!(MalFunc{is_macro,..} if !is_macro,Func(_,_))),
+            "(x)",
+            "True if x is a function and not a macro.",
+            func(fn_is_type!(MalFunc{is_macro,..} if !is_macro,Func(_,_),Closure(_,_))),
         ),
         (
             "macro?",
+            "(x)",
+            "True if x is a macro.",
             func(fn_is_type!(MalFunc{is_macro,..} if is_macro)),
         ),
-        ("pr-str", func(|a| Ok(Str(pr_seq(&a, true, "", "", " "))))),
-        ("str", func(|a| Ok(Str(pr_seq(&a, false, "", "", ""))))),
+        (
+            "pr-str",
+            "(& xs)",
+            "Prints xs readably, separated by spaces, to a string.",
+            func(|a| Ok(Str(pr_seq(&a, true, "", "", " ")))),
+        ),
+        (
+            "str",
+            "(& xs)",
+            "Concatenates the printed forms of xs into a string.",
+            func(|a| Ok(Str(pr_seq(&a, false, "", "", "")))),
+        ),
         (
             "prn",
+            "(& xs)",
+            "Prints xs readably, separated by spaces, followed by a newline.",
             func(|a| {
-                println!("{}", pr_seq(&a, true, "", "", " "));
+                write_line(&pr_seq(&a, true, "", "", " "));
                 Ok(Nil)
             }),
         ),
         (
             "println",
+            "(& xs)",
+            "Prints xs, separated by spaces, followed by a newline.",
             func(|a| {
-                println!("{}", pr_seq(&a, false, "", "", " "));
+                write_line(&pr_seq(&a, false, "", "", " "));
                 Ok(Nil)
             }),
         ),
-        ("read-string", func(fn_str!(|s| { read_str(s) }))),
-        ("readline", func(readline)),
-        ("slurp", func(fn_str!(|f| { slurp(f) }))),
-        ("<", func(fn_t_int_int!(Bool, |i, j| { i < j }))),
-        ("<=", func(fn_t_int_int!(Bool, |i, j| { i <= j }))),
-        (">", func(fn_t_int_int!(Bool, |i, j| { i > j }))),
-        (">=", func(fn_t_int_int!(Bool, |i, j| { i >= j }))),
-        ("+", func(fn_t_int_int!(Int, |i, j| { i + j }))),
-        ("-", func(fn_t_int_int!(Int, |i, j| { i - j }))),
-        ("*", func(fn_t_int_int!(Int, |i, j| { i * j }))),
-        ("/", func(fn_t_int_int!(Int, |i, j| { i / j }))),
-        ("time-ms", func(time_ms)),
-        ("sequential?", func(fn_is_type!(List(_, _), Vector(_, _)))),
-        ("list", func(|a| Ok(list!(a)))),
-        ("list?", func(fn_is_type!(List(_, _)))),
-        ("vector", func(|a| Ok(vector!(a)))),
-        ("vector?", func(fn_is_type!(Vector(_, _)))),
-        ("hash-map", func(|a| hash_map(a))),
-        ("map?", func(fn_is_type!(Hash(_, _)))),
-        ("assoc", func(assoc)),
-        ("dissoc", func(dissoc)),
-        ("get", func(get)),
-        ("contains?", func(contains_q)),
-        ("keys", func(keys)),
-        ("vals", func(vals)),
-        ("vec", func(vec)),
-        ("cons", func(cons)),
-        ("concat", func(concat)),
-        ("empty?", func(|a| a[0].empty_q())),
-        ("nth", func(nth)),
-        ("first", func(first)),
-        ("rest", func(rest)),
-        ("count", func(|a| a[0].count())),
-        ("apply", func(apply)),
-        ("map", func(map)),
-        ("conj", func(conj)),
-        ("seq", func(seq)),
-        ("meta", func(|a| a[0].get_meta())),
-        ("with-meta", func(|a| a[0].clone().with_meta(&a[1]))),
-        ("atom", func(|a| Ok(atom(&a[0])))),
-        ("atom?", func(fn_is_type!(Atom(_)))),
-        ("deref", func(|a| a[0].deref())),
-        ("reset!", func(|a| a[0].reset_bang(&a[1]))),
-        ("swap!", func(|a| a[0].swap_bang(&a[1..].to_vec()))),
+        (
+            "pprint",
+            "(x & width)",
+            "Prints x readably, breaking it over lines that fit in width (80) columns.",
+            func(pprint),
+        ),
+        (
+            "read-string",
+            "(s)",
+            "Reads the first form in the string s.",
+            typed_fn("read-string", read_str),
+        ),
+        (
+            "edn/read-string",
+            "(s)",
+            "Reads the first form in the EDN string s as data, or nil. Unknown tags give tagged literals.",
+            typed_fn("edn/read-string", read_edn),
+        ),
+        (
+            "edn/write-string",
+            "(x)",
+            "Returns x as an EDN string, to be read back with edn/read-string.",
+            func(|a| Ok(Str(a[0].pr_edn()?))),
+        ),
+        (
+            "tagged-literal",
+            "(tag form)",
+            "Returns the tagged literal #tag form. (get t :tag) and (get t :form) return its parts.",
+            func(tagged_literal),
+        ),
+        (
+            "tagged-literal?",
+            "(x)",
+            "True if x is a tagged literal.",
+            func(fn_is_type!(Tagged(..))),
+        ),
+        (
+            "char?",
+            "(x)",
+            "True if x is a character, like the \\a read by edn/read-string.",
+            func(fn_is_type!(Char(_))),
+        ),
+        (
+            "json-encode",
+            "(x & opts)",
+            "Returns x as a JSON string, indented over several lines with {:pretty true}.",
+            func(json_encode),
+        ),
+        (
+            "json-decode",
+            "(s & opts)",
+            "Reads the JSON string s, its object keys as keywords with {:keywordize true}.",
+            func(json_decode),
+        ),
+        (
+            "re-pattern",
+            "(s)",
+            "Returns the regex for the pattern s, like the literal #\"s\".",
+            func(regex_pattern),
+        ),
+        (
+            "re-find",
+            "(re s)",
+            "Returns the first match of re in s, or nil. With groups a match is a vector of the text and its groups.",
+            typed_fn("re-find", re_find),
+        ),
+        (
+            "re-matches",
+            "(re s)",
+            "Returns the match of re against the whole of s, or nil.",
+            typed_fn("re-matches", re_matches),
+        ),
+        (
+            "re-seq",
+            "(re s)",
+            "Returns a list of the successive matches of re in s, or nil.",
+            typed_fn("re-seq", re_seq),
+        ),
+        (
+            "replace",
+            "(s match replacement)",
+            "Replaces the first match of the string or regex match in s. The replacement is a string, where $1 is a group of a regex, or a function of the match.",
+            typed_fn("replace", |s: String, m: MalVal, r: MalVal| {
+                replace("replace", &s, &m, &r, 1)
+            }),
+        ),
+        (
+            "replace-all",
+            "(s match replacement)",
+            "Replaces every match of the string or regex match in s, like replace.",
+            typed_fn("replace-all", |s: String, m: MalVal, r: MalVal| {
+                replace("replace-all", &s, &m, &r, 0)
+            }),
+        ),
+        (
+            "subs",
+            "(s start & end)",
+            "Returns the part of s from index start up to end, or to its end.",
+            func(strings::subs),
+        ),
+        (
+            "split",
+            "(s sep & limit)",
+            "Splits s around the matches of the string or regex sep, into at most limit parts. Without a limit trailing empty strings are dropped.",
+            func(strings::split),
+        ),
+        (
+            "join",
+            "(sep & coll)",
+            "Concatenates the printed forms of the elements of coll, separated by the string sep. (join coll) has no separator.",
+            func(strings::join),
+        ),
+        (
+            "upper-case",
+            "(s)",
+            "Returns s in upper case.",
+            typed_fn("upper-case", |s: String| s.to_uppercase()),
+        ),
+        (
+            "lower-case",
+            "(s)",
+            "Returns s in lower case.",
+            typed_fn("lower-case", |s: String| s.to_lowercase()),
+        ),
+        (
+            "trim",
+            "(s)",
+            "Removes the whitespace at both ends of s.",
+            typed_fn("trim", |s: String| s.trim().to_string()),
+        ),
+        (
+            "triml",
+            "(s)",
+            "Removes the whitespace at the start of s.",
+            typed_fn("triml", |s: String| s.trim_start().to_string()),
+        ),
+        (
+            "trimr",
+            "(s)",
+            "Removes the whitespace at the end of s.",
+            typed_fn("trimr", |s: String| s.trim_end().to_string()),
+        ),
+        (
+            "trim-newline",
+            "(s)",
+            "Removes the newlines and carriage returns at the end of s.",
+            typed_fn("trim-newline", |s: String| s.trim_end_matches(['\n', '\r']).to_string()),
+        ),
+        (
+            "starts-with?",
+            "(s substr)",
+            "True if s starts with substr.",
+            typed_fn("starts-with?", |s: String, t: String| s.starts_with(&t)),
+        ),
+        (
+            "ends-with?",
+            "(s substr)",
+            "True if s ends with substr.",
+            typed_fn("ends-with?", |s: String, t: String| s.ends_with(&t)),
+        ),
+        (
+            "includes?",
+            "(s substr)",
+            "True if substr occurs in s.",
+            typed_fn("includes?", |s: String, t: String| s.contains(&t)),
+        ),
+        (
+            "index-of",
+            "(s value & from)",
+            "Returns the index of the first occurrence of value in s, from index from on, or nil.",
+            func(strings::index_of),
+        ),
+        (
+            "format",
+            "(fmt & args)",
+            "Formats args with the printf-style directives in fmt: %s, %d, %x, %X, %o, %b, %n and %%, with the flags -, 0 and +, a width and a precision for %s.",
+            func(strings::format),
+        ),
+        (
+            "readline",
+            "(prompt)",
+            "Reads a line from the terminal, nil at end of input.",
+            typed_fn("readline", readline),
+        ),
+        (
+            "slurp",
+            "(file)",
+            "Returns the contents of file as a string.",
+            typed_fn("slurp", files::slurp),
+        ),
+        (
+            "spit",
+            "(file x & opts)",
+            "Writes x, printed as str prints it, to file, replacing its contents unless {:append true} is given.",
+            func(spit),
+        ),
+        (
+            "file-exists?",
+            "(path)",
+            "True if a file or directory exists at path.",
+            typed_fn("file-exists?", files::file_exists),
+        ),
+        (
+            "directory?",
+            "(path)",
+            "True if path is a directory.",
+            typed_fn("directory?", files::is_directory),
+        ),
+        (
+            "list-dir",
+            "(dir)",
+            "Returns the sorted names of the entries of dir.",
+            typed_fn("list-dir", files::list_dir),
+        ),
+        (
+            "mkdir",
+            "(dir)",
+            "Creates dir along with any missing parent directories.",
+            typed_fn("mkdir", files::mkdir),
+        ),
+        (
+            "delete-file",
+            "(path)",
+            "Deletes the file or empty directory at path.",
+            typed_fn("delete-file", files::delete_file),
+        ),
+        (
+            "rename",
+            "(from to)",
+            "Moves the file or directory from to to.",
+            typed_fn("rename", files::rename),
+        ),
+        (
+            "read-lines",
+            "(file)",
+            "Returns the lines of file as a list of strings, without their line endings.",
+            typed_fn("read-lines", files::read_lines),
+        ),
+        (
+            "open",
+            "(file & mode)",
+            "Opens file and returns a handle to it. The mode is :read (the default), :write to replace the file or :append to write at its end.",
+            func(open),
+        ),
+        (
+            "read-line",
+            "(handle)",
+            "Reads the next line from a handle opened for reading, nil at the end of the file.",
+            typed_fn("read-line", files::read_line),
+        ),
+        (
+            "write",
+            "(handle x)",
+            "Writes x, printed as str prints it, to a handle opened for writing.",
+            typed_fn("write", files::write),
+        ),
+        (
+            "close",
+            "(handle)",
+            "Closes a handle, writing out anything buffered. Closing it again does nothing.",
+            typed_fn("close", files::close),
+        ),
+        (
+            "<",
+            "(a b)",
+            "True if a is less than b.",
+            typed_fn("<", |i: i64, j: i64| i < j),
+        ),
+        (
+            "<=",
+            "(a b)",
+            "True if a is less than or equal to b.",
+            typed_fn("<=", |i: i64, j: i64| i <= j),
+        ),
+        (
+            ">",
+            "(a b)",
+            "True if a is greater than b.",
+            typed_fn(">", |i: i64, j: i64| i > j),
+        ),
+        (
+            ">=",
+            "(a b)",
+            "True if a is greater than or equal to b.",
+            typed_fn(">=", |i: i64, j: i64| i >= j),
+        ),
+        (
+            "+",
+            "(a b)",
+            "Returns the sum of a and b.",
+            typed_fn("+", |i: i64, j: i64| checked("+", i.checked_add(j))),
+        ),
+        (
+            "-",
+            "(a b)",
+            "Returns a minus b.",
+            typed_fn("-", |i: i64, j: i64| checked("-", i.checked_sub(j))),
+        ),
+        (
+            "*",
+            "(a b)",
+            "Returns the product of a and b.",
+            typed_fn("*", |i: i64, j: i64| checked("*", i.checked_mul(j))),
+        ),
+        (
+            "/",
+            "(a b)",
+            "Returns a divided by b, rounded towards zero.",
+            typed_fn("/", divide),
+        ),
+        (
+            "time-ms",
+            "()",
+            "Returns the current time in milliseconds since the epoch.",
+            func(time_ms),
+        ),
+        (
+            "getenv",
+            "(name)",
+            "Returns the value of the environment variable name, nil if it is not set.",
+            typed_fn("getenv", process::getenv),
+        ),
+        (
+            "setenv",
+            "(name value)",
+            "Sets the environment variable name to the string value, or removes it when value is nil.",
+            func(process::setenv),
+        ),
+        (
+            "exit",
+            "(& code)",
+            "Exits the process with the int status code, 0 by default.",
+            func(process::exit),
+        ),
+        (
+            "sh",
+            "(cmd & args)",
+            "Runs the program cmd with the string args, which may end with the options :in, a string to feed to its stdin, and :dir, the directory to run it in. Returns a map of its :exit code (nil if it was killed by a signal), its :out and its :err.",
+            func(process::sh),
+        ),
+        (
+            "sequential?",
+            "(x)",
+            "True if x is a list or a vector.",
+            func(fn_is_type!(List(_, _), Vector(_, _))),
+        ),
+        (
+            "list",
+            "(& xs)",
+            "Returns a list of xs.",
+            func(|a| Ok(list!(a))),
+        ),
+        (
+            "list?",
+            "(x)",
+            "True if x is a list.",
+            func(fn_is_type!(List(_, _))),
+        ),
+        (
+            "vector",
+            "(& xs)",
+            "Returns a vector of xs.",
+            func(|a| Ok(vector!(a))),
+        ),
+        (
+            "vector?",
+            "(x)",
+            "True if x is a vector.",
+            func(fn_is_type!(Vector(_, _))),
+        ),
+        (
+            "hash-map",
+            "(& kvs)",
+            "Returns a hash-map of the given keys and values.",
+            func(|a| hash_map(a)),
+        ),
+        (
+            "map?",
+            "(x)",
+            "True if x is a hash-map.",
+            func(fn_is_type!(Hash(_, _))),
+        ),
+        (
+            "assoc",
+            "(m & kvs)",
+            "Returns m with the given keys set to the given values.",
+            func(assoc),
+        ),
+        (
+            "dissoc",
+            "(m & ks)",
+            "Returns m without the given keys.",
+            func(dissoc),
+        ),
+        (
+            "get",
+            "(m k)",
+            "Returns the value of k in m, or nil.",
+            func(get),
+        ),
+        (
+            "contains?",
+            "(m k)",
+            "True if m has the key k, or the set m has the element k.",
+            func(contains_q),
+        ),
+        (
+            "set",
+            "(coll)",
+            "Returns a set of the elements of coll.",
+            func(to_set),
+        ),
+        (
+            "set?",
+            "(x)",
+            "True if x is a set.",
+            func(fn_is_type!(Set(_, _))),
+        ),
+        (
+            "disj",
+            "(s & xs)",
+            "Returns the set s without xs.",
+            func(disj),
+        ),
+        (
+            "union",
+            "(& sets)",
+            "Returns a set of the elements of all the sets.",
+            func(union),
+        ),
+        (
+            "intersection",
+            "(s & sets)",
+            "Returns a set of the elements of s that are in all the sets.",
+            func(intersection),
+        ),
+        (
+            "difference",
+            "(s & sets)",
+            "Returns a set of the elements of s that are in none of the sets.",
+            func(difference),
+        ),
+        (
+            "keys",
+            "(m)",
+            "Returns a list of the keys of m.",
+            func(keys),
+        ),
+        (
+            "vals",
+            "(m)",
+            "Returns a list of the values of m.",
+            func(vals),
+        ),
+        (
+            "vec",
+            "(xs)",
+            "Returns a vector with the elements of the sequence xs.",
+            func(vec),
+        ),
+        (
+            "cons",
+            "(x xs)",
+            "Returns a list of x followed by the elements of xs.",
+            typed_fn("cons", cons),
+        ),
+        (
+            "concat",
+            "(& xss)",
+            "Returns a list of the elements of all the sequences xss.",
+            func(concat),
+        ),
+        (
+            "empty?",
+            "(xs)",
+            "True if the sequence xs has no elements.",
+            func(|a| a[0].empty_q()),
+        ),
+        (
+            "nth",
+            "(xs n)",
+            "Returns the element of xs at index n.",
+            typed_fn("nth", nth),
+        ),
+        (
+            "first",
+            "(xs)",
+            "Returns the first element of xs, or nil.",
+            func(first),
+        ),
+        (
+            "rest",
+            "(xs)",
+            "Returns a list of all but the first element of xs.",
+            func(rest),
+        ),
+        (
+            "count",
+            "(xs)",
+            "Returns the number of elements in xs, or of characters in a string.",
+            func(|a| a[0].count()),
+        ),
+        (
+            "apply",
+            "(f & args)",
+            "Calls f with args, the last of which is a sequence spliced into the call.",
+            func(apply),
+        ),
+        (
+            "map",
+            "(f xs)",
+            "Returns a list of the results of calling f on each element of xs.",
+            func(map),
+        ),
+        (
+            "conj",
+            "(coll & xs)",
+            "Adds xs to coll, to the front of a list, the end of a vector or a set.",
+            func(conj),
+        ),
+        (
+            "seq",
+            "(coll)",
+            "Returns a list of the elements or characters of coll, or nil when empty.",
+            func(seq),
+        ),
+        (
+            "meta",
+            "(x)",
+            "Returns the metadata of x.",
+            func(|a| a[0].get_meta()),
+        ),
+        (
+            "with-meta",
+            "(x meta)",
+            "Returns a copy of x with the metadata meta.",
+            func(|a| a[0].clone().with_meta(&a[1])),
+        ),
+        (
+            "atom",
+            "(x)",
+            "Returns a new atom holding x.",
+            func(|a| Ok(atom(&a[0]))),
+        ),
+        (
+            "atom?",
+            "(x)",
+            "True if x is an atom.",
+            func(fn_is_type!(Atom(_))),
+        ),
+        (
+            "deref",
+            "(a)",
+            "Returns the value held by the atom a.",
+            func(|a| a[0].deref()),
+        ),
+        (
+            "reset!",
+            "(a x)",
+            "Sets the value of the atom a to x and returns x.",
+            func(|a| a[0].reset_bang(&a[1])),
+        ),
+        (
+            "swap!",
+            "(a f & args)",
+            "Sets the atom a to the result of calling f with its value and args.",
+            func(|a| a[0].swap_bang(&a[1..].to_vec())),
+        ),
+        (
+            "doc",
+            "(f)",
+            "Prints the signature and documentation of the function f.",
+            func(doc),
+        ),
     ]
 }
+
+pub fn ns() -> Vec<(&'static str, MalVal)> {
+    let mut ns: Vec<(&'static str, MalVal)> = natives()
+        .into_iter()
+        .map(|(name, params, doc, f)| match f {
+            Func(fp, _) => {
+                let (required, variadic) = arity(params);
+                let checked = builtin(name, params, doc, move |a| {
+                    check_arity(name, &a, required, variadic)?;
+                    fp(a)
+                });
+                (name, checked)
+            }
+            // typed functions check their arguments already
+            Closure(c, meta) => match Rc::try_unwrap(c) {
+                Ok(c) => {
+                    let doc = Some((params, doc));
+                    (name, Closure(Rc::new(NativeClosure { doc, ..c }), meta))
+                }
+                Err(c) => (name, Closure(c, meta)),
+            },
+            f => (name, f),
+        })
+        .collect();
+    // each environment has its own printer settings
+    ns.push(("*print-length*", atom(&Nil)));
+    ns.push(("*print-level*", atom(&Nil)));
+    ns
+}
+
+// Define the builtins that look at env itself. They only hold on to env
+// weakly, as it holds them.
+pub fn install(env: &Env) {
+    let e = Rc::downgrade(env);
+    let apropos = builtin(
+        "apropos",
+        "(s)",
+        "Returns the names of the definitions containing the string s.",
+        move |a| {
+            check_arity("apropos", &a, 1, false)?;
+            match e.upgrade() {
+                Some(env) => apropos(&env, a),
+                None => error("the environment was dropped"),
+            }
+        },
+    );
+    env_sets(env, "apropos", apropos);
+}
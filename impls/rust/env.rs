@@ -83,3 +83,16 @@ pub fn env_set(env: &Env, key: MalVal, val: MalVal) -> MalRet {
 pub fn env_sets(env: &Env, key: &str, val: MalVal) {
     env.data.borrow_mut().insert(key.to_string(), val);
 }
+
+// All symbols visible from env, innermost bindings first
+pub fn env_keys(env: &Env) -> Vec<String> {
+    let mut keys: Vec<String> = env.data.borrow().keys().cloned().collect();
+    if let Some(ref o) = env.outer {
+        for k in env_keys(o) {
+            if !env.data.borrow().contains_key(&k) {
+                keys.push(k);
+            }
+        }
+    }
+    keys
+}
@@ -0,0 +1,446 @@
+use std::rc::Rc;
+use std::time::Duration;
+//use std::collections::HashMap;
+use fnv::FnvHashMap;
+use itertools::Itertools;
+
+use crate::budget;
+use crate::budget::Limits;
+use crate::core;
+use crate::env::{env_bind, env_find, env_get, env_new, env_set, env_sets, Env};
+use crate::modules;
+use crate::modules::Loader;
+use crate::printer::with_env;
+use crate::reader;
+use crate::types::MalErr::{ErrInterrupted, ErrLimit, ErrMalVal, ErrString};
+use crate::types::MalVal::{Bool, Closure, Func, Hash, Int, List, MalFunc, Nil, Set, Str, Sym, Vector};
+use crate::types::{error, hash_map, set, MalArgs, MalErr, MalRet, MalVal};
+
+// read
+fn read(str: &str) -> MalRet {
+    reader::read_str(str.to_string())
+}
+
+// eval
+
+fn qq_iter(elts: &MalArgs) -> MalVal {
+    let mut acc = list![];
+    for elt in elts.iter().rev() {
+        if let List(v, _) = elt {
+            if v.len() == 2 {
+                if let Sym(ref s) = v[0] {
+                    if s == "splice-unquote" {
+                        acc = list![Sym("concat".to_string()), v[1].clone(), acc];
+                        continue;
+                    }
+                }
+            }
+        }
+        acc = list![Sym("cons".to_string()), quasiquote(&elt), acc];
+    }
+    return acc;
+}
+
+fn quasiquote(ast: &MalVal) -> MalVal {
+    match ast {
+        List(v, _) => {
+            if v.len() == 2 {
+                if let Sym(ref s) = v[0] {
+                    if s == "unquote" {
+                        return v[1].clone();
+                    }
+                }
+            }
+            return qq_iter(&v);
+        },
+        Vector(v, _) => return list![Sym("vec".to_string()), qq_iter(&v)],
+        Hash(_, _) | Sym(_)=> return list![Sym("quote".to_string()), ast.clone()],
+        _ => ast.clone(),
+    }
+}
+
+fn is_macro_call(ast: &MalVal, env: &Env) -> Option<(MalVal, MalArgs)> {
+    match ast {
+        List(v, _) => match v[0] {
+            Sym(ref s) => match env_find(env, s) {
+                Some(e) => match env_get(&e, &v[0]) {
+                    Ok(f @ MalFunc { is_macro: true, .. }) => Some((f, v[1..].to_vec())),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn macroexpand(mut ast: MalVal, env: &Env) -> (bool, MalRet) {
+    let mut was_expanded = false;
+    while let Some((mf, args)) = is_macro_call(&ast, env) {
+        //println!("macroexpand 1: {:?}", ast);
+        ast = match mf.apply(args) {
+            Err(e) => return (false, Err(e)),
+            Ok(a) => a,
+        };
+        //println!("macroexpand 2: {:?}", ast);
+        was_expanded = true;
+    }
+    ((was_expanded, Ok(ast)))
+}
+
+fn eval_ast(ast: &MalVal, env: &Env) -> MalRet {
+    let ret = match ast {
+        Sym(_) => Ok(env_get(&env, &ast)?),
+        List(v, _) => {
+            let mut lst: MalArgs = vec![];
+            for a in v.iter() {
+                lst.push(eval(a.clone(), env.clone())?)
+            }
+            Ok(list!(lst))
+        }
+        Vector(v, _) => {
+            let mut lst: MalArgs = vec![];
+            for a in v.iter() {
+                lst.push(eval(a.clone(), env.clone())?)
+            }
+            Ok(vector!(lst))
+        }
+        Hash(hm, _) => {
+            let mut new_hm: FnvHashMap<String, MalVal> = FnvHashMap::default();
+            for (k, v) in hm.iter() {
+                new_hm.insert(k.to_string(), eval(v.clone(), env.clone())?);
+            }
+            Ok(Hash(Rc::new(new_hm), Rc::new(Nil)))
+        }
+        Set(s, _) => {
+            let mut lst: MalArgs = vec![];
+            for a in s.iter() {
+                lst.push(eval(a.clone(), env.clone())?)
+            }
+            Ok(set(lst))
+        }
+        _ => return Ok(ast.clone()),
+    };
+    budget::alloc(ret.as_ref().unwrap_or(&Nil))?;
+    ret
+}
+
+fn limits(opts: &MalVal) -> Result<Limits, MalErr> {
+    let mut limits = Limits::default();
+    let hm = match opts {
+        Hash(hm, _) => hm,
+        _ => return Err(ErrString("with-limits: expected a map of limits".to_string())),
+    };
+    for (k, v) in hm.iter() {
+        let n = match v {
+            Int(n) if *n >= 0 => *n as u64,
+            _ => {
+                return Err(ErrString(format!(
+                    "with-limits: {} must be a non-negative integer",
+                    Str(k.to_string()).pr_str(true)
+                )))
+            }
+        };
+        match k.trim_start_matches('\u{29e}') {
+            "steps" => limits.steps = Some(n),
+            "timeout-ms" => limits.timeout = Some(Duration::from_millis(n)),
+            "alloc" => limits.alloc = Some(n),
+            _ => {
+                return Err(ErrString(format!(
+                    "with-limits: unknown limit {}",
+                    Str(k.to_string()).pr_str(true)
+                )))
+            }
+        }
+    }
+    Ok(limits)
+}
+
+// Record the name a documented function is bound to, and its docstring
+// when one is given, in the function's metadata
+fn document(f: MalVal, name: &MalVal, docstring: Option<MalVal>) -> MalRet {
+    let documented = docstring.is_some();
+    let mut hm = match f {
+        MalFunc { ref meta, .. } => match **meta {
+            Hash(ref hm, _) if documented || hm.contains_key("\u{29e}doc") => (**hm).clone(),
+            Nil if documented => FnvHashMap::default(),
+            _ => return Ok(f),
+        },
+        _ => return Ok(f),
+    };
+    if let Some(doc) = docstring {
+        hm.insert("\u{29e}doc".to_string(), doc);
+    }
+    if let Sym(ref s) = name {
+        hm.entry("\u{29e}name".to_string())
+            .or_insert_with(|| Str(s.to_string()));
+    }
+    f.clone().with_meta(&Hash(Rc::new(hm), Rc::new(Nil)))
+}
+
+pub fn eval(mut ast: MalVal, mut env: Env) -> MalRet {
+    let ret: MalRet;
+
+    'tco: loop {
+        budget::tick()?;
+        ret = match ast.clone() {
+            List(l, _) => {
+                if l.len() == 0 {
+                    return Ok(ast);
+                }
+                match macroexpand(ast.clone(), &env) {
+                    (true, Ok(new_ast)) => {
+                        ast = new_ast;
+                        continue 'tco;
+                    }
+                    (_, Err(e)) => return Err(e),
+                    _ => (),
+                }
+
+                if l.len() == 0 {
+                    return Ok(ast);
+                }
+                let a0 = &l[0];
+                match a0 {
+                    Sym(ref a0sym) if a0sym == "def!" => {
+                        let val = eval(l[2].clone(), env.clone())?;
+                        env_set(&env, l[1].clone(), document(val, &l[1], None)?)
+                    }
+                    Sym(ref a0sym) if a0sym == "let*" => {
+                        env = env_new(Some(env.clone()));
+                        let (a1, a2) = (l[1].clone(), l[2].clone());
+                        match a1 {
+                            List(ref binds, _) | Vector(ref binds, _) => {
+                                for (b, e) in binds.iter().tuples() {
+                                    match b {
+                                        Sym(_) => {
+                                            let _ = env_set(
+                                                &env,
+                                                b.clone(),
+                                                eval(e.clone(), env.clone())?,
+                                            );
+                                        }
+                                        _ => {
+                                            return error("let* with non-Sym binding");
+                                        }
+                                    }
+                                }
+                            }
+                            _ => {
+                                return error("let* with non-List bindings");
+                            }
+                        };
+                        ast = a2;
+                        continue 'tco;
+                    }
+                    Sym(ref a0sym) if a0sym == "quote" => Ok(l[1].clone()),
+                    Sym(ref a0sym) if a0sym == "quasiquoteexpand" => Ok(quasiquote(&l[1])),
+                    Sym(ref a0sym) if a0sym == "quasiquote" => {
+                        ast = quasiquote(&l[1]);
+                        continue 'tco;
+                    }
+                    Sym(ref a0sym) if a0sym == "defmacro!" => {
+                        // (defmacro! name "docstring"? fn)
+                        let (a1, a2, docstring) = match l.len() {
+                            4 => (l[1].clone(), l[3].clone(), Some(l[2].clone())),
+                            _ => (l[1].clone(), l[2].clone(), None),
+                        };
+                        let r = eval(a2, env.clone())?;
+                        match r {
+                            MalFunc {
+                                eval,
+                                ast,
+                                env,
+                                params,
+                                meta,
+                                ..
+                            } => Ok(env_set(
+                                &env,
+                                a1.clone(),
+                                document(
+                                    MalFunc {
+                                        eval: eval,
+                                        ast: ast.clone(),
+                                        env: env.clone(),
+                                        params: params.clone(),
+                                        is_macro: true,
+                                        meta: meta.clone(),
+                                    },
+                                    &a1,
+                                    docstring,
+                                )?,
+                            )?),
+                            _ => error("set_macro on non-function"),
+                        }
+                    }
+                    Sym(ref a0sym) if a0sym == "macroexpand" => {
+                        match macroexpand(l[1].clone(), &env) {
+                            (_, Ok(new_ast)) => Ok(new_ast),
+                            (_, e) => return e,
+                        }
+                    }
+                    Sym(ref a0sym) if a0sym == "try*" => match eval(l[1].clone(), env.clone()) {
+                        res @ Err(ErrLimit(_)) if budget::exceeded() => res,
+                        res @ Err(ErrInterrupted) => res,
+                        Err(ref e) if l.len() >= 3 => {
+                            let exc = match e {
+                                ErrMalVal(mv) => mv.clone(),
+                                ErrString(s) => Str(s.to_string()),
+                                ErrLimit(l) => Str(l.to_string()),
+                                ErrInterrupted => unreachable!(),
+                            };
+                            match l[2].clone() {
+                                List(c, _) => {
+                                    let catch_env = env_bind(
+                                        Some(env.clone()),
+                                        list!(vec![c[1].clone()]),
+                                        vec![exc],
+                                    )?;
+                                    eval(c[2].clone(), catch_env)
+                                }
+                                _ => error("invalid catch block"),
+                            }
+                        }
+                        res => res,
+                    },
+                    Sym(ref a0sym) if a0sym == "with-limits" => {
+                        if l.len() < 2 {
+                            return error("with-limits: expected a limits map and a body");
+                        }
+                        let limits = limits(&eval(l[1].clone(), env.clone())?)?;
+                        let body = [vec![Sym("do".to_string())], l[2..].to_vec()].concat();
+                        budget::with_limits(limits, || eval(list!(body), env.clone()))
+                    }
+                    Sym(ref a0sym) if a0sym == "do" => {
+                        match eval_ast(&list!(l[1..l.len() - 1].to_vec()), &env)? {
+                            List(_, _) => {
+                                ast = l.last().unwrap_or(&Nil).clone();
+                                continue 'tco;
+                            }
+                            _ => error("invalid do form"),
+                        }
+                    }
+                    Sym(ref a0sym) if a0sym == "if" => {
+                        let cond = eval(l[1].clone(), env.clone())?;
+                        match cond {
+                            Bool(false) | Nil if l.len() >= 4 => {
+                                ast = l[3].clone();
+                                continue 'tco;
+                            }
+                            Bool(false) | Nil => Ok(Nil),
+                            _ if l.len() >= 3 => {
+                                ast = l[2].clone();
+                                continue 'tco;
+                            }
+                            _ => Ok(Nil),
+                        }
+                    }
+                    Sym(ref a0sym) if a0sym == "fn*" => {
+                        // (fn* params "docstring"? body)
+                        let (a1, a2, meta) = match (l.len(), &l[2]) {
+                            (4, doc @ Str(_)) if !doc.keyword_q() => (
+                                l[1].clone(),
+                                l[3].clone(),
+                                hash_map(vec![Str("\u{29e}doc".to_string()), doc.clone()])?,
+                            ),
+                            _ => (l[1].clone(), l[2].clone(), Nil),
+                        };
+                        Ok(MalFunc {
+                            eval: eval,
+                            ast: Rc::new(a2),
+                            env: env,
+                            params: Rc::new(a1),
+                            is_macro: false,
+                            meta: Rc::new(meta),
+                        })
+                    }
+                    Sym(ref a0sym) if a0sym == "eval" => {
+                        ast = eval(l[1].clone(), env.clone())?;
+                        while let Some(ref e) = env.clone().outer {
+                            env = e.clone();
+                        }
+                        continue 'tco;
+                    }
+                    _ => match eval_ast(&ast, &env)? {
+                        List(ref el, _) => {
+                            let ref f = el[0].clone();
+                            let args = el[1..].to_vec();
+                            match f {
+                                Func(_, _) | Closure(_, _) => {
+                                    let res = f.apply(args)?;
+                                    budget::alloc(&res)?;
+                                    Ok(res)
+                                }
+                                MalFunc {
+                                    ast: mast,
+                                    env: menv,
+                                    params,
+                                    ..
+                                } => {
+                                    let a = &**mast;
+                                    let p = &**params;
+                                    env = env_bind(Some(menv.clone()), p.clone(), args)?;
+                                    ast = a.clone();
+                                    continue 'tco;
+                                }
+                                _ => error("attempt to call non-function"),
+                            }
+                        }
+                        _ => error("expected a list"),
+                    },
+                }
+            }
+            _ => eval_ast(&ast, &env),
+        };
+
+        break;
+    } // end 'tco loop
+
+    ret
+}
+
+// print
+fn print(ast: &MalVal) -> String {
+    ast.pr_str(true)
+}
+
+pub fn re(str: &str, env: &Env) -> MalRet {
+    with_env(env, || {
+        let ast = read(str)?;
+        eval(ast, env.clone())
+    })
+}
+
+pub fn rep(str: &str, env: &Env) -> Result<String, MalErr> {
+    with_env(env, || {
+        let exp = re(str, env)?;
+        Ok(print(&exp))
+    })
+}
+
+// The environment a session starts from, rebuilt by :reset
+pub fn repl_env_new(argv: &[String]) -> Env {
+    repl_env_with_loader(argv).0
+}
+
+// Like repl_env_new, along with the loader that its load-file and require
+// share
+pub fn repl_env_with_loader(argv: &[String]) -> (Env, Loader) {
+    // core.rs: defined using rust
+    let repl_env = env_new(None);
+    for (k, v) in core::ns() {
+        env_sets(&repl_env, k, v);
+    }
+    core::install(&repl_env);
+    let argv = list!(argv.iter().map(|a| Str(a.clone())).collect());
+    env_sets(&repl_env, "*ARGV*", argv.clone());
+    env_sets(&repl_env, "*command-line-args*", argv);
+    let loader = modules::install(&repl_env);
+
+    // core.mal: defined using the language itself
+    let _ = rep("(def! *host-language* \"rust\")", &repl_env);
+    let _ = rep("(def! not (fn* (a) (if a false true)))", &repl_env);
+    let _ = rep("(defmacro! cond (fn* (& xs) (if (> (count xs) 0) (list 'if (first xs) (if (> (count xs) 1) (nth xs 1) (throw \"odd number of forms to cond\")) (cons 'cond (rest (rest xs)))))))", &repl_env);
+    (repl_env, loader)
+}
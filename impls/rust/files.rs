@@ -0,0 +1,175 @@
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::sandbox::{check_read, check_write};
+use crate::types::MalErr::ErrString;
+use crate::types::MalVal::{Bool, Handle, List, Nil, Str};
+use crate::types::{error, FileHandle, FileIo, MalErr, MalRet, MalVal};
+
+// The error of the native name on path, which carries the text of the OS
+// error so that it can be caught and shown
+fn io_error(name: &str, path: &str, e: io::Error) -> MalErr {
+    ErrString(format!("{}: {}: {}", name, path, e))
+}
+
+// The contents of the file path, read on behalf of the builtin name
+pub fn read_file(name: &str, path: &str) -> Result<String, MalErr> {
+    check_read(name, path)?;
+    fs::read_to_string(path).map_err(|e| io_error(name, path, e))
+}
+
+pub fn slurp(path: String) -> MalRet {
+    read_file("slurp", &path).map(Str)
+}
+
+// Writes the printed form of x to path, at its end when append is set
+pub fn spit(path: &str, x: &MalVal, append: bool) -> MalRet {
+    check_write("spit", path)?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path);
+    file.and_then(|mut f| f.write_all(x.pr_str(false).as_bytes()))
+        .map(|_| Nil)
+        .map_err(|e| io_error("spit", path, e))
+}
+
+pub fn file_exists(path: String) -> MalRet {
+    check_read("file-exists?", &path)?;
+    Ok(Bool(Path::new(&path).exists()))
+}
+
+pub fn is_directory(path: String) -> MalRet {
+    check_read("directory?", &path)?;
+    Ok(Bool(Path::new(&path).is_dir()))
+}
+
+// The names of the entries of the directory path, sorted
+pub fn list_dir(path: String) -> MalRet {
+    check_read("list-dir", &path)?;
+    let mut names = vec![];
+    for entry in fs::read_dir(&path).map_err(|e| io_error("list-dir", &path, e))? {
+        let entry = entry.map_err(|e| io_error("list-dir", &path, e))?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(list!(names.into_iter().map(Str).collect()))
+}
+
+// Creates the directory path along with its missing parents
+pub fn mkdir(path: String) -> MalRet {
+    check_write("mkdir", &path)?;
+    fs::create_dir_all(&path)
+        .map(|_| Nil)
+        .map_err(|e| io_error("mkdir", &path, e))
+}
+
+// Deletes the file or empty directory path
+pub fn delete_file(path: String) -> MalRet {
+    check_write("delete-file", &path)?;
+    let res = match Path::new(&path).is_dir() {
+        true => fs::remove_dir(&path),
+        false => fs::remove_file(&path),
+    };
+    res.map(|_| Nil)
+        .map_err(|e| io_error("delete-file", &path, e))
+}
+
+pub fn rename(from: String, to: String) -> MalRet {
+    check_write("rename", &from)?;
+    check_write("rename", &to)?;
+    fs::rename(&from, &to)
+        .map(|_| Nil)
+        .map_err(|e| io_error("rename", &from, e))
+}
+
+// The lines of the file path, without their line endings
+pub fn read_lines(path: String) -> MalRet {
+    check_read("read-lines", &path)?;
+    let file = File::open(&path).map_err(|e| io_error("read-lines", &path, e))?;
+    let lines = BufReader::new(file)
+        .lines()
+        .map(|line| line.map(Str))
+        .collect::<Result<Vec<MalVal>, io::Error>>()
+        .map_err(|e| io_error("read-lines", &path, e))?;
+    Ok(list!(lines))
+}
+
+// Opens path for mode, which is :read, :write to replace the file or
+// :append to write at its end
+pub fn open(path: String, mode: &str) -> MalRet {
+    let file = match mode {
+        "read" => {
+            check_read("open", &path)?;
+            File::open(&path).map(|f| FileIo::Read(BufReader::new(f)))
+        }
+        "write" => {
+            check_write("open", &path)?;
+            File::create(&path).map(|f| FileIo::Write(BufWriter::new(f)))
+        }
+        "append" => {
+            check_write("open", &path)?;
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&path)
+                .map(|f| FileIo::Write(BufWriter::new(f)))
+        }
+        _ => return error(&format!("open: unknown mode :{}", mode)),
+    };
+    let file = file.map_err(|e| io_error("open", &path, e))?;
+    Ok(Handle(Rc::new(FileHandle {
+        path,
+        file: RefCell::new(Some(file)),
+    })))
+}
+
+// The next line of h without its line ending, nil at the end of the file
+pub fn read_line(h: Rc<FileHandle>) -> MalRet {
+    match *h.file.borrow_mut() {
+        Some(FileIo::Read(ref mut r)) => {
+            let mut line = String::new();
+            match r.read_line(&mut line) {
+                Ok(0) => Ok(Nil),
+                Ok(_) => {
+                    if line.ends_with('\n') {
+                        line.pop();
+                        if line.ends_with('\r') {
+                            line.pop();
+                        }
+                    }
+                    Ok(Str(line))
+                }
+                Err(e) => Err(io_error("read-line", &h.path, e)),
+            }
+        }
+        Some(FileIo::Write(_)) => error(&format!("read-line: {} is not open for reading", h.path)),
+        None => error(&format!("read-line: {} is closed", h.path)),
+    }
+}
+
+// Writes the printed form of x to h
+pub fn write(h: Rc<FileHandle>, x: MalVal) -> MalRet {
+    match *h.file.borrow_mut() {
+        Some(FileIo::Write(ref mut w)) => w
+            .write_all(x.pr_str(false).as_bytes())
+            .map(|_| Nil)
+            .map_err(|e| io_error("write", &h.path, e)),
+        Some(FileIo::Read(_)) => error(&format!("write: {} is not open for writing", h.path)),
+        None => error(&format!("write: {} is closed", h.path)),
+    }
+}
+
+// Closes h, writing out what is buffered. Closing it again does nothing.
+pub fn close(h: Rc<FileHandle>) -> MalRet {
+    let file = h.file.borrow_mut().take();
+    if let Some(FileIo::Write(mut w)) = file {
+        w.flush().map_err(|e| io_error("close", &h.path, e))?;
+    }
+    Ok(Nil)
+}
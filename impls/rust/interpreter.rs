@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use crate::env::{env_get, env_sets, Env};
+use crate::eval::{eval, repl_env_with_loader};
+use crate::modules::Loader;
+use crate::printer::with_env;
+use crate::reader::read_forms;
+use crate::sandbox::{with_capabilities, Capabilities};
+use crate::types::MalVal::{Nil, Sym};
+use crate::types::{closure, typed_fn, MalArgs, MalRet, MalVal, NativeFn};
+
+// A mal interpreter to embed in Rust programs. Each one has its own global
+// environment, holding the core functions and the prelude, and the
+// capabilities its I/O builtins are limited to.
+pub struct Interpreter {
+    env: Env,
+    caps: Capabilities,
+    loader: Loader,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::with_args(&[])
+    }
+
+    // An interpreter whose *ARGV* is argv
+    pub fn with_args(argv: &[String]) -> Interpreter {
+        Interpreter::with_capabilities(argv, Capabilities::all())
+    }
+
+    // An interpreter for scripts that may only use caps. Denied calls throw
+    // a permission error.
+    pub fn with_capabilities(argv: &[String], caps: Capabilities) -> Interpreter {
+        let (env, loader) = repl_env_with_loader(argv);
+        Interpreter { env, caps, loader }
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    // Evaluate all the forms in src, returning the value of the last one
+    pub fn eval_str(&self, src: &str) -> MalRet {
+        with_capabilities(self.caps.clone(), || {
+            with_env(&self.env, || {
+                let mut ret = Nil;
+                for (_, form) in read_forms(src) {
+                    ret = eval(form?, self.env.clone())?;
+                }
+                Ok(ret)
+            })
+        })
+    }
+
+    // Evaluate the file path like load-file, so that the modules it
+    // requires are found next to it, returning the value of its last form
+    pub fn load_file<P: AsRef<Path>>(&self, path: P) -> MalRet {
+        let path = path.as_ref().to_string_lossy();
+        with_capabilities(self.caps.clone(), || {
+            with_env(&self.env, || self.loader.load_file(&path))
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<MalVal> {
+        env_get(&self.env, &Sym(name.to_string())).ok()
+    }
+
+    pub fn set(&self, name: &str, val: MalVal) {
+        env_sets(&self.env, name, val);
+    }
+
+    // Make a Rust function or closure callable from mal as name
+    pub fn register<F: Fn(MalArgs) -> MalRet + 'static>(&self, name: &str, f: F) {
+        self.set(name, closure(name, f));
+    }
+
+    // Make a function of typed arguments, like fn(i64, String) -> String,
+    // callable from mal as name. Calls with the wrong number or types of
+    // arguments throw an error.
+    pub fn register_fn<Args, F: NativeFn<Args> + 'static>(&self, name: &str, f: F) {
+        self.set(name, typed_fn(name, f));
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
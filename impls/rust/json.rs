@@ -0,0 +1,157 @@
+use std::fmt;
+use std::rc::Rc;
+
+use fnv::FnvHashMap;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, Serializer};
+
+use crate::types::MalErr::ErrString;
+use crate::types::MalVal::{Bool, Char, Hash, Int, List, Nil, Set, Str, Sym, Vector};
+use crate::types::{MalErr, MalRet, MalVal};
+
+// The name of a keyword, or the string itself
+fn key_name(s: &str) -> &str {
+    s.trim_start_matches('\u{29e}')
+}
+
+// Keywords and symbols are written as strings holding their name,
+// characters as one character strings and sets as arrays. The keys of maps
+// are sorted, so the output doesn't change between runs. Functions and
+// atoms have no JSON form.
+impl Serialize for MalVal {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Nil => s.serialize_unit(),
+            Bool(b) => s.serialize_bool(*b),
+            Int(i) => s.serialize_i64(*i),
+            Str(st) | Sym(st) => s.serialize_str(key_name(st)),
+            Char(c) => s.serialize_char(*c),
+            List(v, _) | Vector(v, _) => s.collect_seq(v.iter()),
+            Set(v, _) => s.collect_seq(v.iter()),
+            Hash(hm, _) => {
+                let mut keys: Vec<&String> = hm.keys().collect();
+                keys.sort();
+                let mut map = s.serialize_map(Some(keys.len()))?;
+                for k in keys {
+                    map.serialize_entry(key_name(k), &hm[k])?;
+                }
+                map.end()
+            }
+            _ => Err(ser::Error::custom(format!(
+                "cannot encode {}",
+                self.pr_str(true)
+            ))),
+        }
+    }
+}
+
+struct MalVisitor;
+
+// Arrays are read as vectors and objects as hash-maps with string keys.
+// There are no floats, so only numbers with an integer value are accepted.
+impl<'de> Visitor<'de> for MalVisitor {
+    type Value = MalVal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_unit<E>(self) -> Result<MalVal, E> {
+        Ok(Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<MalVal, E> {
+        Ok(Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<MalVal, D::Error> {
+        MalVal::deserialize(d)
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<MalVal, E> {
+        Ok(Bool(b))
+    }
+
+    fn visit_i64<E>(self, i: i64) -> Result<MalVal, E> {
+        Ok(Int(i))
+    }
+
+    fn visit_u64<E: de::Error>(self, u: u64) -> Result<MalVal, E> {
+        if u > i64::MAX as u64 {
+            return Err(E::custom(format!("{} is too large", u)));
+        }
+        Ok(Int(u as i64))
+    }
+
+    fn visit_f64<E: de::Error>(self, f: f64) -> Result<MalVal, E> {
+        if f.fract() != 0.0 || f.abs() > i64::MAX as f64 {
+            return Err(E::custom(format!("{} is not an integer", f)));
+        }
+        Ok(Int(f as i64))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<MalVal, E> {
+        Ok(Str(s.to_string()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<MalVal, E> {
+        Ok(Str(s))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<MalVal, A::Error> {
+        let mut v = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(mv) = seq.next_element()? {
+            v.push(mv);
+        }
+        Ok(vector!(v))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<MalVal, A::Error> {
+        let mut hm = FnvHashMap::default();
+        while let Some((k, v)) = map.next_entry::<String, MalVal>()? {
+            hm.insert(k, v);
+        }
+        Ok(Hash(Rc::new(hm), Rc::new(Nil)))
+    }
+}
+
+impl<'de> Deserialize<'de> for MalVal {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<MalVal, D::Error> {
+        d.deserialize_any(MalVisitor)
+    }
+}
+
+// The keys of the maps in mv turned into keywords
+fn keywordize(mv: MalVal) -> MalVal {
+    match mv {
+        Vector(v, _) => vector!(v.iter().cloned().map(keywordize).collect()),
+        Hash(hm, _) => {
+            let hm: FnvHashMap<String, MalVal> = hm
+                .iter()
+                .map(|(k, v)| (format!("\u{29e}{}", k), keywordize(v.clone())))
+                .collect();
+            Hash(Rc::new(hm), Rc::new(Nil))
+        }
+        mv => mv,
+    }
+}
+
+fn json_error(name: &str, e: serde_json::Error) -> MalErr {
+    ErrString(format!("{}: {}", name, e))
+}
+
+pub fn encode(mv: &MalVal, pretty: bool) -> MalRet {
+    let res = match pretty {
+        true => serde_json::to_string_pretty(mv),
+        false => serde_json::to_string(mv),
+    };
+    res.map(Str).map_err(|e| json_error("json-encode", e))
+}
+
+pub fn decode(s: &str, keywordize_keys: bool) -> MalRet {
+    let mv = serde_json::from_str(s).map_err(|e| json_error("json-decode", e))?;
+    match keywordize_keys {
+        true => Ok(keywordize(mv)),
+        false => Ok(mv),
+    }
+}
@@ -0,0 +1,35 @@
+// The interpreter of stepA as a library, for embedding mal in Rust programs
+
+#[macro_use]
+extern crate lazy_static;
+extern crate fnv;
+extern crate itertools;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate rustyline;
+
+#[macro_use]
+pub mod types;
+pub mod budget;
+pub mod env;
+pub mod printer;
+pub mod reader;
+pub mod repl;
+pub mod bencode;
+pub mod server;
+pub mod json;
+pub mod strings;
+pub mod files;
+pub mod sandbox;
+pub mod process;
+#[macro_use]
+pub mod core;
+pub mod eval;
+mod modules;
+mod interpreter;
+pub mod lint;
+
+pub use crate::interpreter::Interpreter;
+pub use crate::sandbox::{Access, Capabilities};
+pub use crate::types::{MalArgs, MalErr, MalRet, MalVal};
@@ -0,0 +1,395 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::budget::{with_limits, Limits};
+use crate::core;
+use crate::env::{env_find, Env};
+use crate::eval::{eval, macroexpand};
+use crate::reader::{read_forms, tokens};
+use crate::repl::SPECIAL_FORMS;
+use crate::sandbox::{with_capabilities, Capabilities};
+use crate::types::format_error;
+use crate::types::MalVal;
+use crate::types::MalVal::{Hash, List, Set, Str, Sym, Vector};
+
+pub struct Warning {
+    pub line: usize,
+    pub message: String,
+}
+
+// A local binding, with its line when it is found in the source
+struct Local {
+    name: String,
+    line: Option<usize>,
+    used: bool,
+}
+
+// Finds the source line of a symbol, the nth lookup of a name giving the
+// line of its nth occurrence in the current top-level form
+struct Locator<'a> {
+    src: &'a str,
+    tokens: Vec<(Range<usize>, String)>,
+    seen: HashMap<String, usize>,
+    range: Range<usize>,
+}
+
+impl<'a> Locator<'a> {
+    fn new(src: &'a str) -> Locator<'a> {
+        Locator {
+            src,
+            tokens: tokens(src),
+            seen: HashMap::new(),
+            range: 0..0,
+        }
+    }
+
+    fn form(&mut self, range: &Range<usize>) {
+        self.seen.clear();
+        self.range = range.clone();
+    }
+
+    fn line_at(&self, offset: usize) -> usize {
+        self.src[..offset].matches('\n').count() + 1
+    }
+
+    fn find(&mut self, name: &str) -> Option<usize> {
+        let n = self.seen.entry(name.to_string()).or_insert(0);
+        let range = &self.range;
+        let found = self
+            .tokens
+            .iter()
+            .filter(|(r, t)| range.start <= r.start && r.end <= range.end && t == name)
+            .nth(*n);
+        *n += 1;
+        found.map(|(r, _)| r.start)
+    }
+
+    fn line(&mut self, name: &str) -> usize {
+        let offset = self.find(name).unwrap_or(self.range.start);
+        self.line_at(offset)
+    }
+}
+
+struct Linter<'a> {
+    env: Env,
+    globals: HashSet<String>,
+    core: HashMap<&'static str, &'static str>,
+    scopes: Vec<Vec<Local>>,
+    locator: Locator<'a>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, line: usize, message: String) {
+        self.warnings.push(Warning { line, message });
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|s| s.iter().any(|l| l.name == name))
+    }
+
+    fn reference(&mut self, name: &str) {
+        let line = self.locator.line(name);
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(local) = scope.iter_mut().rev().find(|l| l.name == name) {
+                local.used = true;
+                return;
+            }
+        }
+        if !self.globals.contains(name) && env_find(&self.env, name).is_none() {
+            self.warn(line, format!("unbound symbol {}", name));
+        }
+    }
+
+    fn shadows(&mut self, name: &str, line: usize) {
+        if self.core.contains_key(name) {
+            self.warn(line, format!("{} shadows the core function {}", name, name));
+        }
+    }
+
+    fn bind(&mut self, name: &MalVal) -> Option<Local> {
+        match name {
+            Sym(s) if s != "&" => {
+                let line = self.locator.find(s).map(|o| self.locator.line_at(o));
+                self.shadows(
+                    s,
+                    line.unwrap_or_else(|| self.locator.line_at(self.locator.range.start)),
+                );
+                Some(Local {
+                    name: s.clone(),
+                    line,
+                    used: false,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, local: Option<Local>) {
+        if let (Some(local), Some(scope)) = (local, self.scopes.last_mut()) {
+            scope.push(local);
+        }
+    }
+
+    // Bindings that the source never mentions come from macros
+    fn pop_scope(&mut self, report: bool) {
+        let scope = self.scopes.pop().unwrap_or_default();
+        for local in scope {
+            if !report || local.used || local.name.starts_with('_') {
+                continue;
+            }
+            if let Some(line) = local.line {
+                self.warn(line, format!("unused local {}", local.name));
+            }
+        }
+    }
+
+    fn forms(&mut self, asts: &[MalVal]) {
+        for ast in asts {
+            self.form(ast);
+        }
+    }
+
+    fn form(&mut self, ast: &MalVal) {
+        let l = match ast {
+            Sym(s) => return self.reference(s),
+            Vector(v, _) => return self.forms(v),
+            Hash(h, _) => {
+                let vals: Vec<MalVal> = h.values().cloned().collect();
+                return self.forms(&vals);
+            }
+            Set(s, _) => {
+                let items: Vec<MalVal> = s.iter().cloned().collect();
+                return self.forms(&items);
+            }
+            List(l, _) if !l.is_empty() => l,
+            _ => return,
+        };
+        let head = match l[0] {
+            Sym(ref s) if self.is_local(s) => None,
+            Sym(ref s) => Some(s.as_str()),
+            _ => None,
+        };
+        if head.is_some() {
+            match with_limits(limits(), || macroexpand(ast.clone(), &self.env)) {
+                (true, Ok(expanded)) => return self.form(&expanded),
+                (_, Err(e)) => {
+                    let line = self.locator.line(head.unwrap_or(""));
+                    let msg = format!("macro expansion failed: {}", format_error(e));
+                    return self.warn(line, msg);
+                }
+                _ => (),
+            }
+        }
+        match head {
+            Some("def!") | Some("defmacro!") if l.len() > 2 => {
+                if let Sym(ref name) = l[1] {
+                    let line = self.locator.line(name);
+                    self.shadows(name, line);
+                }
+                self.form(&l[l.len() - 1]);
+            }
+            Some("let*") => self.let_star(l),
+            Some("fn*") if l.len() > 2 => {
+                self.scopes.push(vec![]);
+                if let List(ref params, _) | Vector(ref params, _) = l[1] {
+                    for p in params.iter() {
+                        let local = self.bind(p);
+                        self.push(local);
+                    }
+                }
+                match (l.len(), &l[2]) {
+                    (4, Str(_)) => self.form(&l[3]),
+                    _ => self.forms(&l[2..]),
+                }
+                self.pop_scope(false);
+            }
+            Some("quote") | Some("macroexpand") => (),
+            Some("quasiquote") if l.len() > 1 => self.unquoted(&l[1]),
+            Some("try*") => {
+                self.forms(&l[1..2.min(l.len())]);
+                if let Some(List(c, _)) = l.get(2) {
+                    if c.len() > 2 {
+                        self.scopes.push(vec![]);
+                        let local = self.bind(&c[1]);
+                        self.push(local);
+                        self.forms(&c[2..]);
+                        self.pop_scope(false);
+                    }
+                }
+            }
+            Some(s) if SPECIAL_FORMS.contains(&s) => self.forms(&l[1..]),
+            Some(s) => {
+                self.reference(s);
+                self.check_arity(s, l.len() - 1);
+                self.forms(&l[1..]);
+            }
+            None => self.forms(l),
+        }
+    }
+
+    fn let_star(&mut self, l: &[MalVal]) {
+        let line = self.locator.line("let*");
+        let binds = match l.get(1) {
+            Some(List(b, _)) | Some(Vector(b, _)) => b.clone(),
+            _ => return self.warn(line, "let* bindings must be a list or vector".to_string()),
+        };
+        if binds.len() % 2 == 1 {
+            self.warn(line, "let* has an odd number of binding forms".to_string());
+        }
+        // the values are evaluated in the new environment, so functions can
+        // refer to any of the bindings
+        self.scopes.push(vec![]);
+        for pair in binds.chunks(2) {
+            match pair[0] {
+                Sym(_) => {}
+                ref b => {
+                    let msg = format!("let* binding name must be a symbol, got {}", b.pr_str(true));
+                    self.warn(line, msg);
+                }
+            }
+            let local = self.bind(&pair[0]);
+            self.push(local);
+        }
+        for pair in binds.chunks(2) {
+            self.forms(&pair[1..]);
+        }
+        self.forms(&l[2..]);
+        self.pop_scope(true);
+    }
+
+    // Only the unquoted parts of a quasiquoted form are evaluated
+    fn unquoted(&mut self, ast: &MalVal) {
+        match ast {
+            List(l, _)
+                if l.len() == 2
+                    && (l[0] == Sym("unquote".to_string())
+                        || l[0] == Sym("splice-unquote".to_string())) =>
+            {
+                self.form(&l[1])
+            }
+            List(l, _) | Vector(l, _) => {
+                for a in l.iter() {
+                    self.unquoted(a);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn check_arity(&mut self, name: &str, args: usize) {
+        if self.globals.contains(name) {
+            return;
+        }
+        let (required, variadic) = match self.core.get(name) {
+            Some(params) => core::arity(params),
+            None => return,
+        };
+        if args < required || (!variadic && args > required) {
+            let expected = match variadic {
+                true => format!("at least {}", required),
+                false => required.to_string(),
+            };
+            let line = self.locator.line(name);
+            let msg = format!(
+                "wrong number of arguments to {}: expected {}, got {}",
+                name, expected, args
+            );
+            self.warn(line, msg);
+        }
+    }
+}
+
+// The names a form defines at the top level
+fn defined(ast: &MalVal, names: &mut HashSet<String>) {
+    if let List(l, _) = ast {
+        match (l.first(), l.get(1)) {
+            (Some(Sym(ref s)), Some(Sym(ref name))) if s == "def!" || s == "defmacro!" => {
+                names.insert(name.clone());
+            }
+            (Some(Sym(ref s)), _) if s == "do" => {
+                for a in l[1..].iter() {
+                    defined(a, names);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// Macros and the functions and constants they may use when they are
+// expanded, the only top-level forms that are evaluated since defining them
+// has no side effects
+fn is_pure_definition(ast: &MalVal) -> bool {
+    let l = match ast {
+        List(l, _) if l.len() > 2 => l,
+        _ => return false,
+    };
+    match (&l[0], &l[l.len() - 1]) {
+        (Sym(s), _) if s == "defmacro!" => true,
+        (Sym(s), List(f, _)) if s == "def!" => f.first() == Some(&Sym("fn*".to_string())),
+        (Sym(s), Vector(..)) | (Sym(s), Hash(..)) if s == "def!" => false,
+        (Sym(s), _) => s == "def!",
+        _ => false,
+    }
+}
+
+// The bounds on each macro expansion and definition that is run. The
+// steps also bound how deep a runaway recursion goes.
+fn limits() -> Limits {
+    Limits {
+        steps: Some(5_000),
+        timeout: Some(Duration::from_secs(1)),
+        alloc: Some(1_000_000),
+    }
+}
+
+// Check the source of a mal program, whose macros and functions are
+// defined in env as they are found so later forms can be expanded. They
+// run without any capabilities and within limits(), so linting a program
+// can't touch the host or hang; what they are refused is reported.
+pub fn lint(src: &str, env: &Env) -> Vec<Warning> {
+    with_capabilities(Capabilities::none(), || lint_forms(src, env))
+}
+
+fn lint_forms(src: &str, env: &Env) -> Vec<Warning> {
+    let forms = read_forms(src);
+    let mut globals = HashSet::new();
+    for (_, form) in &forms {
+        if let Ok(ast) = form {
+            defined(ast, &mut globals);
+        }
+    }
+    let mut linter = Linter {
+        env: env.clone(),
+        globals,
+        core: core::natives()
+            .into_iter()
+            .map(|(n, p, _, _)| (n, p))
+            .collect(),
+        scopes: vec![],
+        locator: Locator::new(src),
+        warnings: vec![],
+    };
+    for (range, form) in forms {
+        linter.locator.form(&range);
+        let ast = match form {
+            Ok(ast) => ast,
+            Err(e) => {
+                let line = linter.locator.line_at(range.start);
+                linter.warn(line, format_error(e));
+                continue;
+            }
+        };
+        linter.form(&ast);
+        if is_pure_definition(&ast) {
+            if let Err(e) = with_limits(limits(), || eval(ast.clone(), env.clone())) {
+                let line = linter.locator.line_at(range.start);
+                linter.warn(line, format_error(e));
+            }
+        }
+    }
+    linter.warnings.sort_by_key(|w| w.line);
+    linter.warnings
+}
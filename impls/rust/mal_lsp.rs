@@ -0,0 +1,527 @@
+// A language server for mal source files, speaking JSON-RPC over stdio
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use regex::Regex;
+
+#[macro_use]
+extern crate lazy_static;
+extern crate regex;
+#[macro_use]
+extern crate serde_json;
+use serde_json::Value;
+
+extern crate mal;
+use mal::core::describe;
+use mal::reader::{read_str, tokens};
+use mal::repl::SPECIAL_FORMS;
+use mal::types::MalVal;
+use mal::types::MalVal::{List, Str, Sym};
+use mal::{core, types};
+
+// LSP constants
+const SEVERITY_ERROR: u32 = 1;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_VARIABLE: u32 = 13;
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_KEYWORD: u32 = 14;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// The longest message body a client may send, so that a bogus length can't
+// make us allocate without bound
+const MAX_MESSAGE_LEN: usize = 64 * 1024 * 1024;
+
+struct Diagnostic {
+    range: Range<usize>,
+    message: String,
+}
+
+// A top-level def! or defmacro!
+struct Def {
+    name: String,
+    name_range: Range<usize>,
+    range: Range<usize>,
+    is_fn: bool,
+}
+
+// What the server knows about an open document
+struct Document {
+    text: String,
+    tokens: Vec<(Range<usize>, String)>,
+    diagnostics: Vec<Diagnostic>,
+    defs: Vec<Def>,
+}
+
+fn closer(open: &str) -> &'static str {
+    match open {
+        "(" => ")",
+        "[" => "]",
+        _ => "}",
+    }
+}
+
+fn analyze(text: String) -> Document {
+    lazy_static! {
+        static ref STR_RE: Regex = Regex::new(r#"^"(?:\\.|[^\\"])*"$"#).unwrap();
+    }
+    let toks: Vec<(Range<usize>, String)> = tokens(&text)
+        .into_iter()
+        .filter(|(_, t)| !t.starts_with(';'))
+        .collect();
+    let mut diagnostics = vec![];
+    let mut forms = vec![];
+    // open delimiters, the first token of the current top-level form and
+    // the number of forms still needed to complete it
+    let mut stack: Vec<usize> = vec![];
+    let mut start = None;
+    let mut pending = 0;
+    for (i, (range, tok)) in toks.iter().enumerate() {
+        if start.is_none() {
+            start = Some(i);
+            pending = 1;
+        }
+        let completes = match tok.as_str() {
+            "(" | "[" | "{" | "#{" => {
+                stack.push(i);
+                false
+            }
+            ")" | "]" | "}" => match stack.last() {
+                Some(&o) if closer(&toks[o].1) == tok => {
+                    stack.pop();
+                    stack.is_empty()
+                }
+                _ => {
+                    diagnostics.push(Diagnostic {
+                        range: range.clone(),
+                        message: format!("unexpected '{}'", tok),
+                    });
+                    if start == Some(i) {
+                        start = None;
+                    }
+                    false
+                }
+            },
+            "'" | "`" | "~" | "~@" | "@" => false,
+            "^" => {
+                if stack.is_empty() {
+                    pending += 1;
+                }
+                false
+            }
+            t => {
+                // Strings and regex literals
+                let s = t.strip_prefix('#').unwrap_or(t);
+                if s.starts_with('"') && !STR_RE.is_match(s) {
+                    diagnostics.push(Diagnostic {
+                        range: range.clone(),
+                        message: "expected '\"', got EOF".to_string(),
+                    });
+                }
+                stack.is_empty()
+            }
+        };
+        if completes {
+            pending -= 1;
+            if pending == 0 {
+                forms.push(start.take().unwrap()..i + 1);
+            }
+        }
+    }
+    for &o in &stack {
+        diagnostics.push(Diagnostic {
+            range: toks[o].0.clone(),
+            message: format!("expected '{}', got EOF", closer(&toks[o].1)),
+        });
+    }
+
+    let mut defs = vec![];
+    for form in forms {
+        let range = toks[form.start].0.start..toks[form.end - 1].0.end;
+        if diagnostics
+            .iter()
+            .any(|d| d.range.start < range.end && range.start < d.range.end)
+        {
+            continue;
+        }
+        if let Err(e) = read_str(text[range.clone()].to_string()) {
+            diagnostics.push(Diagnostic {
+                range,
+                message: types::format_error(e),
+            });
+            continue;
+        }
+        let word = |i: usize| toks.get(form.start + i).map(|(_, t)| t.as_str());
+        let is_def = word(1) == Some("def!") || word(1) == Some("defmacro!");
+        if word(0) == Some("(") && is_def && form.len() > 3 {
+            let (name_range, name) = toks[form.start + 2].clone();
+            defs.push(Def {
+                name,
+                name_range,
+                range,
+                is_fn: word(3) == Some("(") && word(4) == Some("fn*")
+                    || word(1) == Some("defmacro!"),
+            });
+        }
+    }
+    Document {
+        tokens: toks,
+        text,
+        diagnostics,
+        defs,
+    }
+}
+
+// Positions are lines and UTF-16 code units
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, r: &Range<usize>) -> Value {
+    json!({"start": position(text, r.start), "end": position(text, r.end)})
+}
+
+fn offset(text: &str, pos: &Value) -> usize {
+    let line = pos["line"].as_u64().unwrap_or(0) as usize;
+    let character = pos["character"].as_u64().unwrap_or(0) as usize;
+    let line_start = match line {
+        0 => 0,
+        n => text
+            .match_indices('\n')
+            .nth(n - 1)
+            .map_or(text.len(), |(i, _)| i + 1),
+    };
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+// The symbol token under the cursor, which may also be right after it
+fn symbol_at(doc: &Document, off: usize) -> Option<&(Range<usize>, String)> {
+    doc.tokens.iter().find(|(r, t)| {
+        r.start <= off && off <= r.end && !t.starts_with(|c| "()[]{}'`~^@\"#".contains(c))
+    })
+}
+
+// The signature and docstring of a top-level definition
+fn def_doc(doc: &Document, def: &Def) -> (String, Option<String>) {
+    let form = read_str(doc.text[def.range.clone()].to_string());
+    let f = match form {
+        Ok(List(ref l, _)) => match l.len() {
+            4 => l[3].clone(),
+            3 => l[2].clone(),
+            _ => return (def.name.clone(), None),
+        },
+        _ => return (def.name.clone(), None),
+    };
+    let docstring = match form {
+        Ok(List(ref l, _)) if l.len() == 4 => match l[2] {
+            Str(ref s) => Some(s.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    match f {
+        List(ref l, _) if l.len() >= 3 && l[0] == Sym("fn*".to_string()) => {
+            let params = l[1].pr_str(true);
+            let sig = match &params[1..params.len() - 1] {
+                "" => format!("({})", def.name),
+                p => format!("({} {})", def.name, p),
+            };
+            let docstring = match (l.len(), &l[2]) {
+                (4, Str(s)) => Some(s.clone()),
+                _ => docstring,
+            };
+            (sig, docstring)
+        }
+        _ => (def.name.clone(), docstring),
+    }
+}
+
+fn native(name: &str) -> Option<MalVal> {
+    core::ns()
+        .into_iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, f)| f)
+}
+
+fn hover(doc: &Document, off: usize) -> Value {
+    let name = match symbol_at(doc, off) {
+        Some((_, name)) => name,
+        None => return Value::Null,
+    };
+    let (sig, docstring) = if let Some(def) = doc.defs.iter().find(|d| &d.name == name) {
+        def_doc(doc, def)
+    } else if SPECIAL_FORMS.contains(&name.as_str()) {
+        (name.clone(), Some("special form".to_string()))
+    } else if let Some(f) = native(name) {
+        match describe(&f, name) {
+            Ok(text) => {
+                let mut lines = text.lines();
+                let sig = lines.next().unwrap_or("").to_string();
+                let rest: Vec<&str> = lines.map(|l| l.trim()).collect();
+                (sig, Some(rest.join("\n")))
+            }
+            Err(_) => (f.pr_str(true), None),
+        }
+    } else {
+        return Value::Null;
+    };
+    let mut value = format!("```mal\n{}\n```", sig);
+    if let Some(d) = docstring {
+        value.push_str("\n\n");
+        value.push_str(&d);
+    }
+    json!({"contents": {"kind": "markdown", "value": value}})
+}
+
+fn definition(docs: &HashMap<String, Document>, uri: &str, off: usize) -> Value {
+    let name = match docs.get(uri).and_then(|doc| symbol_at(doc, off)) {
+        Some((_, name)) => name,
+        None => return Value::Null,
+    };
+    // the current document first
+    let mut uris: Vec<&String> = docs.keys().filter(|u| *u != uri).collect();
+    uris.sort();
+    for u in docs
+        .get_key_value(uri)
+        .map(|(k, _)| k)
+        .into_iter()
+        .chain(uris)
+    {
+        let doc = &docs[u];
+        if let Some(def) = doc.defs.iter().find(|d| &d.name == name) {
+            return json!({"uri": u, "range": range(&doc.text, &def.name_range)});
+        }
+    }
+    Value::Null
+}
+
+fn document_symbols(doc: &Document) -> Value {
+    let symbols: Vec<Value> = doc
+        .defs
+        .iter()
+        .map(|def| {
+            json!({
+                "name": def.name,
+                "kind": if def.is_fn { SYMBOL_FUNCTION } else { SYMBOL_VARIABLE },
+                "range": range(&doc.text, &def.range),
+                "selectionRange": range(&doc.text, &def.name_range),
+            })
+        })
+        .collect();
+    Value::Array(symbols)
+}
+
+fn completion(doc: &Document, off: usize) -> Value {
+    let prefix = match symbol_at(doc, off) {
+        Some((r, name)) => &name[..off - r.start],
+        None => "",
+    };
+    let mut items = vec![];
+    for form in SPECIAL_FORMS.iter() {
+        items.push(json!({"label": form, "kind": COMPLETION_KEYWORD}));
+    }
+    for (name, f) in core::ns() {
+        let detail = describe(&f, name)
+            .ok()
+            .and_then(|d| d.lines().next().map(String::from));
+        items.push(json!({"label": name, "kind": COMPLETION_FUNCTION, "detail": detail}));
+    }
+    for def in &doc.defs {
+        let kind = if def.is_fn {
+            COMPLETION_FUNCTION
+        } else {
+            COMPLETION_VARIABLE
+        };
+        let (sig, _) = def_doc(doc, def);
+        items.push(json!({"label": def.name, "kind": kind, "detail": sig}));
+    }
+    items.retain(|item| {
+        item["label"]
+            .as_str()
+            .is_some_and(|l| l.starts_with(prefix))
+    });
+    Value::Array(items)
+}
+
+fn publish(out: &mut dyn Write, uri: &str, doc: Option<&Document>) -> io::Result<()> {
+    let diagnostics: Vec<Value> = doc.map_or(vec![], |doc| {
+        doc.diagnostics
+            .iter()
+            .map(|d| {
+                json!({
+                    "range": range(&doc.text, &d.range),
+                    "severity": SEVERITY_ERROR,
+                    "source": "mal",
+                    "message": d.message,
+                })
+            })
+            .collect()
+    });
+    send(
+        out,
+        &json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics},
+        }),
+    )
+}
+
+fn bad_message(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// Messages are framed by a Content-Length header. A malformed message is an
+// InvalidData error, after which the next message can still be read.
+fn receive(input: &mut dyn BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        // the header may follow what is left of a message that was skipped
+        if let Some(i) = line.find("Content-Length:") {
+            let n = &line[i + "Content-Length:".len()..];
+            length = n.trim().parse::<usize>().ok();
+        }
+    }
+    let length = match length {
+        Some(n) if n <= MAX_MESSAGE_LEN => n,
+        Some(_) => return Err(bad_message("message too long")),
+        None => return Err(bad_message("missing or bad Content-Length")),
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn send(out: &mut dyn Write, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut docs: HashMap<String, Document> = HashMap::new();
+    let mut shutdown = false;
+
+    loop {
+        let msg = match receive(&mut input) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            // a bad message is skipped rather than ending the session
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("mal_lsp: skipping a bad message: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let method = msg["method"].as_str().unwrap_or("");
+        let params = &msg["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+        let off = |docs: &HashMap<String, Document>| {
+            docs.get(&uri)
+                .map(|doc| offset(&doc.text, &params["position"]))
+        };
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": {"name": "mal_lsp"},
+            }),
+            "shutdown" => {
+                shutdown = true;
+                Value::Null
+            }
+            "exit" => std::process::exit(if shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = match method {
+                    "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+                    _ => params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str()),
+                };
+                if let Some(text) = text {
+                    docs.insert(uri.clone(), analyze(text.to_string()));
+                    publish(&mut out, &uri, docs.get(&uri))?;
+                }
+                continue;
+            }
+            "textDocument/didClose" => {
+                docs.remove(&uri);
+                publish(&mut out, &uri, None)?;
+                continue;
+            }
+            "textDocument/hover" => match off(&docs) {
+                Some(off) => hover(&docs[&uri], off),
+                None => Value::Null,
+            },
+            "textDocument/definition" => match off(&docs) {
+                Some(off) => definition(&docs, &uri, off),
+                None => Value::Null,
+            },
+            "textDocument/documentSymbol" => match docs.get(&uri) {
+                Some(doc) => document_symbols(doc),
+                None => Value::Null,
+            },
+            "textDocument/completion" => match off(&docs) {
+                Some(off) => completion(&docs[&uri], off),
+                None => Value::Null,
+            },
+            _ if msg.get("id").is_some() => {
+                send(
+                    &mut out,
+                    &json!({
+                        "jsonrpc": "2.0",
+                        "id": msg["id"],
+                        "error": {"code": METHOD_NOT_FOUND, "message": format!("unknown method {}", method)},
+                    }),
+                )?;
+                continue;
+            }
+            // other notifications
+            _ => continue,
+        };
+        if msg.get("id").is_some() {
+            send(
+                &mut out,
+                &json!({"jsonrpc": "2.0", "id": msg["id"], "result": result}),
+            )?;
+        }
+    }
+    Ok(())
+}
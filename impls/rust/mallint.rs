@@ -0,0 +1,56 @@
+// Report likely mistakes in mal programs without running them
+
+extern crate mal;
+
+use std::fs;
+use std::process::exit;
+use std::thread;
+
+use mal::{lint, Interpreter};
+
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// The warnings about src, checked against the core environment and
+// prelude. The macros of the program run while it is checked, on a thread
+// with a stack deep enough for the recursion that the lint limits allow.
+fn lint_file(src: String) -> Vec<(usize, String)> {
+    let linter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let interp = Interpreter::new();
+            lint::lint(&src, interp.env())
+                .into_iter()
+                .map(|w| (w.line, w.message))
+                .collect()
+        });
+    match linter.map(|t| t.join()) {
+        Ok(Ok(warnings)) => warnings,
+        _ => vec![(1, "the linter failed".to_string())],
+    }
+}
+
+fn main() {
+    let files: Vec<String> = std::env::args().skip(1).collect();
+    if files.is_empty() {
+        eprintln!("usage: mallint FILE...");
+        exit(2);
+    }
+    let mut found = false;
+    for file in &files {
+        let src = match fs::read_to_string(file) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                found = true;
+                continue;
+            }
+        };
+        for (line, message) in lint_file(src) {
+            println!("{}:{}: {}", file, line, message);
+            found = true;
+        }
+    }
+    if found {
+        exit(1);
+    }
+}
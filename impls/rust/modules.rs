@@ -0,0 +1,176 @@
+use std::cell::RefCell;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+use fnv::FnvHashSet;
+use itertools::Itertools;
+
+use crate::env::{env_sets, Env, EnvStruct};
+use crate::eval::eval;
+use crate::files::read_file;
+use crate::reader::read_forms;
+use crate::sandbox::check_read;
+use crate::types::MalErr::ErrString;
+use crate::types::MalVal::{Nil, Str, Sym};
+use crate::types::{arg, builtin, check_arity, error, MalArgs, MalErr, MalRet, MalVal};
+
+// The files that load-file and require are loading into an environment and
+// the modules require has loaded there. Each environment has its own, so
+// that every interpreter and every :reset loads its modules afresh.
+#[derive(Default)]
+struct Modules {
+    // Innermost last. Relative module names are resolved against the
+    // directory of the innermost file.
+    loading: Vec<PathBuf>,
+    loaded: FnvHashSet<PathBuf>,
+}
+
+// The absolute path of path, with symlinks resolved when it exists, so that
+// a file has the same path however it was named
+fn absolute(path: &Path) -> PathBuf {
+    match path.canonicalize() {
+        Ok(p) => p,
+        Err(_) => env::current_dir()
+            .map(|d| d.join(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+// The file of the module name, found in base and then, unless the name is
+// explicitly relative, in the directories of MAL_PATH. The .mal extension
+// may be left out. Only the places the sandbox lets require read are
+// probed; when the module is in none of them, the first refusal is
+// reported.
+fn resolve(name: &str, base: &Path) -> Result<PathBuf, MalErr> {
+    let file = match name.ends_with(".mal") {
+        true => PathBuf::from(name),
+        false => PathBuf::from(format!("{}.mal", name)),
+    };
+    let mut dirs = vec![base.to_path_buf()];
+    if !file.is_absolute() && !name.starts_with("./") && !name.starts_with("../") {
+        if let Some(path) = env::var_os("MAL_PATH") {
+            dirs.extend(env::split_paths(&path).filter(|d| !d.as_os_str().is_empty()));
+        }
+    }
+    let mut denied = None;
+    for f in dirs.iter().map(|d| d.join(&file)) {
+        match check_read("require", &f.to_string_lossy()) {
+            Ok(()) if f.is_file() => return Ok(f),
+            Ok(()) => {}
+            Err(e) => {
+                denied.get_or_insert(e);
+            }
+        }
+    }
+    Err(denied.unwrap_or_else(|| {
+        ErrString(format!(
+            "require: module not found: {} (searched {})",
+            name,
+            dirs.iter().map(|d| d.display()).join(", ")
+        ))
+    }))
+}
+
+// Evaluates src, the contents of the file path, in env, returning the
+// value of its last form
+fn load(modules: &RefCell<Modules>, env: &Env, path: PathBuf, src: &str) -> MalRet {
+    modules.borrow_mut().loading.push(path);
+    let res = read_forms(src)
+        .into_iter()
+        .try_fold(Nil, |_, (_, form)| eval(form?, env.clone()));
+    modules.borrow_mut().loading.pop();
+    res
+}
+
+fn load_file(modules: &RefCell<Modules>, env: &Env, path: &str) -> MalRet {
+    let src = read_file("load-file", path)?;
+    load(modules, env, absolute(Path::new(path)), &src)
+}
+
+fn require(modules: &RefCell<Modules>, env: &Env, a: MalArgs) -> MalRet {
+    let name = match a[0] {
+        Str(ref s) if !a[0].keyword_q() => s.to_string(),
+        Sym(ref s) => s.to_string(),
+        _ => return error("require: module name must be a string or a symbol"),
+    };
+    let base = match modules.borrow().loading.last().and_then(|f| f.parent()) {
+        Some(dir) => dir.to_path_buf(),
+        None => absolute(Path::new(".")),
+    };
+    let file = resolve(&name, &base)?;
+    let path = absolute(&file);
+    {
+        let m = modules.borrow();
+        if m.loaded.contains(&path) {
+            return Ok(Nil);
+        }
+        if let Some(i) = m.loading.iter().position(|p| *p == path) {
+            return error(&format!(
+                "require: circular dependency: {}",
+                m.loading[i..]
+                    .iter()
+                    .chain(Some(&path))
+                    .map(|p| p.display())
+                    .join(" -> ")
+            ));
+        }
+    }
+    let src = read_file("require", &file.to_string_lossy())?;
+    load(modules, env, path.clone(), &src)?;
+    modules.borrow_mut().loaded.insert(path);
+    Ok(Nil)
+}
+
+fn global(env: &Weak<EnvStruct>) -> Result<Env, MalErr> {
+    env.upgrade()
+        .ok_or_else(|| ErrString("the environment was dropped".to_string()))
+}
+
+// Loads files into the environment it was installed in, sharing the
+// modules of its load-file and require
+pub struct Loader {
+    modules: Rc<RefCell<Modules>>,
+    env: Weak<EnvStruct>,
+}
+
+impl Loader {
+    // Evaluates the file path like load-file, returning the value of its
+    // last form
+    pub fn load_file(&self, path: &str) -> MalRet {
+        load_file(&self.modules, &global(&self.env)?, path)
+    }
+}
+
+// Define load-file and require in env. They only hold on to env weakly, as
+// it holds them.
+pub fn install(env: &Env) -> Loader {
+    let modules = Rc::new(RefCell::new(Modules::default()));
+    let (m, e) = (modules.clone(), Rc::downgrade(env));
+    let load_file: MalVal = builtin(
+        "load-file",
+        "(path)",
+        "Evaluates the forms of the file path. Modules it requires by a relative name are searched next to it.",
+        move |a| {
+            check_arity("load-file", &a, 1, false)?;
+            let path: String = arg("load-file", &a, 0)?;
+            load_file(&m, &global(&e)?, &path).map(|_| Nil)
+        },
+    );
+    let (m, e) = (modules.clone(), Rc::downgrade(env));
+    let require: MalVal = builtin(
+        "require",
+        "(name)",
+        "Loads the module name, a string or a symbol, unless it is loaded already. It is found next to the file requiring it, then in the directories of MAL_PATH.",
+        move |a| {
+            check_arity("require", &a, 1, false)?;
+            require(&m, &global(&e)?, a)
+        },
+    );
+    env_sets(env, "load-file", load_file);
+    env_sets(env, "require", require);
+    Loader {
+        modules,
+        env: Rc::downgrade(env),
+    }
+}
@@ -1,5 +1,96 @@
-use crate::types::MalVal;
-use crate::types::MalVal::{Atom, Bool, Func, Hash, Int, List, MalFunc, Nil, Str, Sym, Vector};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::env::{env_get, Env};
+use crate::types::MalErr::ErrString;
+use crate::types::MalVal::{
+    Atom, Bool, Char, Closure, Func, Handle, Hash, Int, List, MalFunc, Nil, Regex, Set, Str, Sym,
+    Tagged, Vector,
+};
+use crate::types::{MalErr, MalVal};
+
+thread_local! {
+    // The environments of the interpreters that are running, innermost
+    // last. The innermost one holds the atoms *print-length* and
+    // *print-level*: when set to an integer, (reset! *print-length* n)
+    // prints only the first n elements of each collection and
+    // (reset! *print-level* n) prints collections nested deeper than n
+    // levels as #
+    static ENVS: RefCell<Vec<Env>> = const { RefCell::new(Vec::new()) };
+    // How deep in collections the printer currently is
+    static LEVEL: Cell<usize> = const { Cell::new(0) };
+    // The atoms being printed, to print a cycle back to one of them as
+    // #<atom ...>
+    static ATOMS: RefCell<Vec<*const RefCell<MalVal>>> = const { RefCell::new(Vec::new()) };
+    // Collects the output of prn and friends instead of stdout when set
+    static CAPTURE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// Print a line of program output
+pub fn write_line(s: &str) {
+    CAPTURE.with(|c| match *c.borrow_mut() {
+        Some(ref mut out) => {
+            out.push_str(s);
+            out.push('\n');
+        }
+        None => println!("{}", s),
+    })
+}
+
+// Run `f`, returning what it printed along with its result
+pub fn capture<T, F: FnOnce() -> T>(f: F) -> (T, String) {
+    let outer = CAPTURE.with(|c| c.replace(Some(String::new())));
+    let res = f();
+    let out = CAPTURE.with(|c| c.replace(outer)).unwrap_or_default();
+    (res, out)
+}
+
+struct EnvGuard;
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        ENVS.with(|e| e.borrow_mut().pop());
+    }
+}
+
+// Run `f` printing with the settings of env
+pub fn with_env<T, F: FnOnce() -> T>(env: &Env, f: F) -> T {
+    ENVS.with(|e| e.borrow_mut().push(env.clone()));
+    let _guard = EnvGuard;
+    f()
+}
+
+// The value of the setting name in the innermost environment
+fn limit(name: &str) -> Option<usize> {
+    let env = ENVS.with(|e| e.borrow().last().cloned())?;
+    match env_get(&env, &Sym(name.to_string())) {
+        Ok(Atom(v)) => match *v.borrow() {
+            Int(n) if n >= 0 => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// True when the collections at the current level are too deep to print
+fn too_deep() -> bool {
+    limit("*print-level*").is_some_and(|max| LEVEL.with(|l| l.get()) >= max)
+}
+
+// How many of `len` elements to print, `chunk` of them counting as one
+// (e.g. a key and its value)
+fn shown(len: usize, chunk: usize) -> usize {
+    limit("*print-length*").map_or(len, |max| len.min(max * chunk))
+}
+
+// Run `f` one level deeper in the printed structure
+fn nested<T, F: FnOnce() -> T>(f: F) -> T {
+    let level = LEVEL.with(|l| l.get());
+    LEVEL.with(|l| l.set(level + 1));
+    let res = f();
+    LEVEL.with(|l| l.set(level));
+    res
+}
 
 fn escape_str(s: &str) -> String {
     s.chars()
@@ -30,6 +121,8 @@ impl MalVal {
                     s.clone()
                 }
             }
+            Char(c) if print_readably => char_literal(*c),
+            Char(c) => c.to_string(),
             Sym(s) => s.clone(),
             List(l, _) => pr_seq(&**l, print_readably, "(", ")", " "),
             Vector(l, _) => pr_seq(&**l, print_readably, "[", "]", " "),
@@ -38,13 +131,31 @@ impl MalVal {
                     .iter()
                     .flat_map(|(k, v)| vec![Str(k.to_string()), v.clone()])
                     .collect();
-                pr_seq(&l, print_readably, "{", "}", " ")
+                pr_coll(&l, 2, print_readably, "{", "}")
             }
+            Set(s, _) => {
+                let l: Vec<MalVal> = s.iter().cloned().collect();
+                pr_coll(&l, 1, print_readably, "#{", "}")
+            }
+            Tagged(t, v) => format!("#{} {}", t, v.pr_str(true)),
+            Regex(r) if print_readably => format!("#\"{}\"", r.as_str().replace('"', "\\\"")),
+            Regex(r) => r.as_str().to_string(),
             Func(f, _) => format!("#<fn {:?}>", f),
+            Closure(c, _) => format!("#<fn {}>", c.name),
             MalFunc {
                 ast: a, params: p, ..
             } => format!("(fn* {} {})", p.pr_str(true), a.pr_str(true)),
-            Atom(a) => format!("(atom {})", a.borrow().pr_str(true)),
+            Atom(a) => {
+                let ptr = Rc::as_ptr(a);
+                if ATOMS.with(|atoms| atoms.borrow().contains(&ptr)) {
+                    return String::from("#<atom ...>");
+                }
+                ATOMS.with(|atoms| atoms.borrow_mut().push(ptr));
+                let s = format!("(atom {})", a.borrow().pr_str(true));
+                ATOMS.with(|atoms| atoms.borrow_mut().pop());
+                s
+            }
+            Handle(h) => format!("#<file {}>", h.path),
         }
     }
 }
@@ -56,6 +167,296 @@ pub fn pr_seq(
     end: &str,
     join: &str,
 ) -> String {
+    // a bare sequence, like the arguments of str or prn, is not a collection
+    if !start.is_empty() {
+        return pr_coll(seq, 1, print_readably, start, end);
+    }
     let strs: Vec<String> = seq.iter().map(|x| x.pr_str(print_readably)).collect();
     format!("{}{}{}", start, strs.join(join), end)
 }
+
+fn pr_coll(seq: &[MalVal], chunk: usize, print_readably: bool, start: &str, end: &str) -> String {
+    if too_deep() {
+        return String::from("#");
+    }
+    let n = shown(seq.len(), chunk);
+    let mut strs: Vec<String> =
+        nested(|| seq[..n].iter().map(|x| x.pr_str(print_readably)).collect());
+    if n < seq.len() {
+        strs.push(String::from("..."));
+    }
+    format!("{}{}{}", start, strs.join(" "), end)
+}
+
+// Forms that keep their first n arguments on the line of the head and
+// indent the rest as a body
+fn body_args(head: &str) -> Option<usize> {
+    match head {
+        "do" | "try*" => Some(0),
+        "def!" | "defmacro!" | "let*" | "fn*" | "if" | "catch*" | "with-limits" => Some(1),
+        _ => None,
+    }
+}
+
+fn column(out: &str) -> usize {
+    let start = out.rfind('\n').map_or(0, |i| i + 1);
+    out[start..].chars().count()
+}
+
+fn newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+}
+
+impl MalVal {
+    // Like pr_str(true), but breaks the collections that don't fit in
+    // `width` columns over several lines
+    pub fn pprint(&self, width: usize) -> String {
+        let mut out = String::new();
+        pp(self, &measure(self), width, &mut out);
+        out
+    }
+}
+
+impl MalVal {
+    // The EDN text of a value, which fails for functions and atoms. Unlike
+    // pr_str it ignores *print-length* and *print-level*, so that it can
+    // be read back.
+    pub fn pr_edn(&self) -> Result<String, MalErr> {
+        let elements = |items: Vec<&MalVal>, start: &str, end: &str| {
+            let strs = items
+                .into_iter()
+                .map(|mv| mv.pr_edn())
+                .collect::<Result<Vec<String>, MalErr>>()?;
+            Ok(format!("{}{}{}", start, strs.join(" "), end))
+        };
+        match self {
+            Str(s) if !self.keyword_q() => Ok(format!("\"{}\"", escape_edn(s))),
+            Nil | Bool(_) | Int(_) | Str(_) | Char(_) | Sym(_) => Ok(self.pr_str(true)),
+            List(l, _) => elements(l.iter().collect(), "(", ")"),
+            Vector(l, _) => elements(l.iter().collect(), "[", "]"),
+            Set(s, _) => elements(s.iter().collect(), "#{", "}"),
+            Hash(hm, _) => {
+                let keys: Vec<MalVal> = hm.keys().map(|k| Str(k.to_string())).collect();
+                let kvs = keys.iter().zip(hm.values()).flat_map(|(k, v)| vec![k, v]);
+                elements(kvs.collect(), "{", "}")
+            }
+            Tagged(t, v) => Ok(format!("#{} {}", t, v.pr_edn()?)),
+            _ => Err(ErrString(format!(
+                "cannot write {} as EDN",
+                self.pr_str(true)
+            ))),
+        }
+    }
+}
+
+// The character c as it is written in EDN, like \a, \newline or \u0007
+fn char_literal(c: char) -> String {
+    match c {
+        '\n' => String::from("\\newline"),
+        ' ' => String::from("\\space"),
+        '\t' => String::from("\\tab"),
+        '\r' => String::from("\\return"),
+        '\u{8}' => String::from("\\backspace"),
+        '\u{c}' => String::from("\\formfeed"),
+        c if c.is_control() => format!("\\u{:04x}", c as u32),
+        c => format!("\\{}", c),
+    }
+}
+
+fn escape_edn(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\n' => "\\n".to_string(),
+            '\t' => "\\t".to_string(),
+            '\r' => "\\r".to_string(),
+            '\\' => "\\\\".to_string(),
+            _ => c.to_string(),
+        })
+        .collect()
+}
+
+// The width of a value printed flat, along with the measures of the
+// elements that pp may break it over several lines into. Measuring the
+// whole value once, bottom-up, spares pp from printing each subform again
+// at every level it is nested in.
+struct Measure {
+    width: usize,
+    items: Vec<Measure>,
+}
+
+fn measure(mv: &MalVal) -> Measure {
+    match mv {
+        List(l, _) | Vector(l, _) => measure_coll(l, 1, 2),
+        Hash(..) | Set(..) => {
+            let (items, chunk, start) = elements(mv);
+            measure_coll(&items, chunk, start.len() + 1)
+        }
+        _ => Measure {
+            width: mv.pr_str(true).chars().count(),
+            items: vec![],
+        },
+    }
+}
+
+// Like pr_coll, with `delims` the width of the delimiters
+fn measure_coll(seq: &[MalVal], chunk: usize, delims: usize) -> Measure {
+    if too_deep() {
+        return Measure {
+            width: 1,
+            items: vec![],
+        };
+    }
+    let n = shown(seq.len(), chunk);
+    let items: Vec<Measure> = nested(|| seq[..n].iter().map(measure).collect());
+    let mut width = delims + items.iter().map(|m| m.width).sum::<usize>() + n.saturating_sub(1);
+    if n < seq.len() {
+        width += if n == 0 { 3 } else { 4 };
+    }
+    Measure { width, items }
+}
+
+// The elements of a hash-map, each key followed by its value, or of a set,
+// with how many make an entry and the opening delimiter
+fn elements(mv: &MalVal) -> (Vec<MalVal>, usize, &'static str) {
+    match mv {
+        Hash(hm, _) => {
+            let kvs = hm
+                .iter()
+                .flat_map(|(k, v)| vec![Str(k.to_string()), v.clone()])
+                .collect();
+            (kvs, 2, "{")
+        }
+        Set(s, _) => (s.iter().cloned().collect(), 1, "#{"),
+        _ => (vec![], 1, ""),
+    }
+}
+
+fn pp(mv: &MalVal, m: &Measure, width: usize, out: &mut String) {
+    if column(out) + m.width <= width {
+        out.push_str(&mv.pr_str(true));
+        return;
+    }
+    let items = &m.items;
+    match mv {
+        List(l, _) if !l.is_empty() && !too_deep() && shown(l.len(), 1) == l.len() => {
+            nested(|| pp_list(l, items, width, out))
+        }
+        List(l, _) if !l.is_empty() && !too_deep() => {
+            nested(|| pp_seq(l, items, "(", ")", width, out))
+        }
+        Vector(v, _) if !v.is_empty() && !too_deep() => {
+            nested(|| pp_seq(v, items, "[", "]", width, out))
+        }
+        Hash(hm, _) if !hm.is_empty() && !too_deep() => {
+            let (kvs, _, _) = elements(mv);
+            nested(|| pp_pairs(&kvs, items, "{", "}", width, out))
+        }
+        Set(s, _) if !s.is_empty() && !too_deep() => {
+            let (l, _, _) = elements(mv);
+            nested(|| pp_seq(&l, items, "#{", "}", width, out))
+        }
+        _ => out.push_str(&mv.pr_str(true)),
+    }
+}
+
+// One element per line, aligned after the opening delimiter. Sequences
+// of scalars are filled instead, as many elements per line as fit.
+fn pp_seq(
+    seq: &[MalVal],
+    items: &[Measure],
+    start: &str,
+    end: &str,
+    width: usize,
+    out: &mut String,
+) {
+    let fill = seq
+        .iter()
+        .all(|mv| !matches!(mv, List(..) | Vector(..) | Hash(..) | Set(..)));
+    out.push_str(start);
+    let indent = column(out);
+    for (i, (mv, m)) in seq.iter().zip(items).enumerate() {
+        if i > 0 && fill && column(out) + 1 + m.width <= width {
+            out.push(' ');
+        } else if i > 0 {
+            newline(out, indent);
+        }
+        pp(mv, m, width, out);
+    }
+    if items.len() < seq.len() {
+        out.push_str(" ...");
+    }
+    out.push_str(end);
+}
+
+// One key and value (or binding) per line
+fn pp_pairs(
+    seq: &[MalVal],
+    items: &[Measure],
+    start: &str,
+    end: &str,
+    width: usize,
+    out: &mut String,
+) {
+    out.push_str(start);
+    let indent = column(out);
+    for (i, (mv, m)) in seq.iter().zip(items).enumerate() {
+        if i > 0 && i % 2 == 0 {
+            newline(out, indent);
+        } else if i > 0 {
+            out.push(' ');
+        }
+        pp(mv, m, width, out);
+    }
+    if items.len() < seq.len() {
+        out.push_str(" ...");
+    }
+    out.push_str(end);
+}
+
+fn pp_list(l: &[MalVal], items: &[Measure], width: usize, out: &mut String) {
+    let head = match l[0] {
+        Sym(ref s) => s,
+        _ => return pp_seq(l, items, "(", ")", width, out),
+    };
+    let indent = column(out);
+    out.push('(');
+    out.push_str(head);
+    match body_args(head) {
+        Some(n) => {
+            for (i, (mv, m)) in l[1..].iter().zip(&items[1..]).enumerate() {
+                if i < n {
+                    out.push(' ');
+                    match mv {
+                        Vector(v, _) if head == "let*" && !too_deep() => {
+                            // *print-length* counts the bindings in pairs,
+                            // so more of them may be shown
+                            let pairs = (m.items.len() < v.len()).then(|| measure_coll(v, 2, 2));
+                            let items = pairs.as_ref().map_or(&m.items, |p| &p.items);
+                            nested(|| pp_pairs(v, items, "[", "]", width, out))
+                        }
+                        _ => pp(mv, m, width, out),
+                    }
+                } else {
+                    newline(out, indent + 2);
+                    pp(mv, m, width, out);
+                }
+            }
+        }
+        // (f a
+        //    b)
+        None => {
+            let align = column(out) + 1;
+            for (i, (mv, m)) in l[1..].iter().zip(&items[1..]).enumerate() {
+                if i == 0 {
+                    out.push(' ');
+                } else {
+                    newline(out, align);
+                }
+                pp(mv, m, width, out);
+            }
+        }
+    }
+    out.push(')');
+}
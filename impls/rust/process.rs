@@ -0,0 +1,113 @@
+use std::env;
+use std::io::Write;
+use std::process::{self, Command, Stdio};
+use std::thread;
+
+use crate::sandbox::check_process;
+use crate::types::MalErr::ErrString;
+use crate::types::MalVal::{Int, Nil, Str};
+use crate::types::{arg, error, hash_map, MalArgs, MalRet};
+
+pub fn getenv(name: String) -> MalRet {
+    check_process("getenv")?;
+    Ok(env::var(&name).map_or(Nil, Str))
+}
+
+// Sets the environment variable name to value, or removes it when value is
+// nil, for this process and the programs it runs
+pub fn setenv(a: MalArgs) -> MalRet {
+    let name: String = arg("setenv", &a, 0)?;
+    check_process("setenv")?;
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return error(&format!("setenv: invalid variable name {:?}", name));
+    }
+    match a[1] {
+        Nil => env::remove_var(&name),
+        _ => {
+            let value: String = arg("setenv", &a, 1)?;
+            if value.contains('\0') {
+                return error("setenv: value contains a NUL character");
+            }
+            env::set_var(&name, value);
+        }
+    }
+    Ok(Nil)
+}
+
+pub fn exit(a: MalArgs) -> MalRet {
+    let code: i64 = match a.first() {
+        Some(_) => arg("exit", &a, 0)?,
+        None => 0,
+    };
+    check_process("exit")?;
+    process::exit(code as i32)
+}
+
+// Runs the program cmd with the string arguments that follow it, which may
+// end with the options :in, a string to feed to its stdin, and :dir, the
+// directory to run it in. Waits for it to finish and returns its exit code,
+// nil if it was killed by a signal, and what it printed.
+pub fn sh(a: MalArgs) -> MalRet {
+    let cmd: String = arg("sh", &a, 0)?;
+    let mut command = Command::new(&cmd);
+    let mut input = None;
+    let mut i = 1;
+    while i < a.len() {
+        match a[i] {
+            Str(ref s) if !a[i].keyword_q() => {
+                command.arg(s);
+                i += 1;
+                continue;
+            }
+            Str(ref k) if i + 1 < a.len() => match &k[2..] {
+                "in" => input = Some(arg::<String>("sh", &a, i + 1)?),
+                "dir" => {
+                    command.current_dir(arg::<String>("sh", &a, i + 1)?);
+                }
+                o => return error(&format!("sh: unknown option :{}", o)),
+            },
+            _ => {
+                return error(&format!(
+                    "sh: expected a string argument or an option, got {}",
+                    a[i].pr_str(true)
+                ))
+            }
+        }
+        i += 2;
+    }
+    check_process("sh")?;
+
+    let stdin = match input {
+        Some(_) => Stdio::piped(),
+        None => Stdio::null(),
+    };
+    let mut child = command
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ErrString(format!("sh: {}: {}", cmd, e)))?;
+    // Feed stdin from another thread, so that a program that prints a lot
+    // before reading all of it can't block us both
+    let feeder = match (input, child.stdin.take()) {
+        (Some(input), Some(mut stdin)) => {
+            Some(thread::spawn(move || stdin.write_all(input.as_bytes())))
+        }
+        _ => None,
+    };
+    let output = child
+        .wait_with_output()
+        .map_err(|e| ErrString(format!("sh: {}: {}", cmd, e)))?;
+    if let Some(feeder) = feeder {
+        // A program may exit without reading all of its input
+        let _ = feeder.join();
+    }
+    hash_map(vec![
+        Str("\u{29e}exit".to_string()),
+        output.status.code().map_or(Nil, |c| Int(c as i64)),
+        Str("\u{29e}out".to_string()),
+        Str(String::from_utf8_lossy(&output.stdout).into_owned()),
+        Str("\u{29e}err".to_string()),
+        Str(String::from_utf8_lossy(&output.stderr).into_owned()),
+    ])
+}
@@ -1,14 +1,18 @@
+use fnv::FnvHashSet;
 use regex::{Captures, Regex};
+use std::ops::Range;
 use std::rc::Rc;
 
 use crate::types::MalErr::ErrString;
-use crate::types::MalVal::{Bool, Int, List, Nil, Str, Sym, Vector};
-use crate::types::{error, hash_map, MalErr, MalRet, MalVal};
+use crate::types::MalVal::{Bool, Char, Int, List, Nil, Str, Sym, Tagged, Vector};
+use crate::types::{error, hash_map, re_pattern, set, MalErr, MalRet, MalVal};
 
 #[derive(Debug, Clone)]
 struct Reader {
     tokens: Vec<String>,
     pos: usize,
+    // Reading EDN data rather than mal code
+    edn: bool,
 }
 
 impl Reader {
@@ -29,20 +33,44 @@ impl Reader {
     }
 }
 
-fn tokenize(str: &str) -> Vec<String> {
+// The tokens of str, comments included, with the byte range of each
+pub fn tokens(str: &str) -> Vec<(Range<usize>, String)> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r###"[\s,]*(~@|#\{|#?"(?:\\.|[^\\"])*"?|[\[\]{}()'`~^@]|;.*|[^\s\[\]{}('"`,;)]+)"###
+        )
+        .unwrap();
+    }
+
+    RE.captures_iter(str)
+        .filter_map(|cap| cap.get(1))
+        .map(|m| (m.start()..m.end(), m.as_str().to_string()))
+        .collect()
+}
+
+// The tokens of EDN, which adds characters like \a, #{ for sets, #_ to
+// discard a form and #tag for tagged literals
+fn edn_tokens(str: &str) -> Vec<(Range<usize>, String)> {
     lazy_static! {
         static ref RE: Regex = Regex::new(
-            r###"[\s,]*(~@|[\[\]{}()'`~^@]|"(?:\\.|[^\\"])*"?|;.*|[^\s\[\]{}('"`,;)]+)"###
+            r###"[\s,]*(#\{|#_|#[^\s\[\]{}('"`,;)]*|\\(?:u[0-9a-fA-F]{4}|[a-z]+|.)|~@|[\[\]{}()'`~^@]|"(?:\\.|[^\\"])*"?|;.*|[^\s\[\]{}('"`,;)]+)"###
         )
         .unwrap();
     }
 
+    RE.captures_iter(str)
+        .filter_map(|cap| cap.get(1))
+        .map(|m| (m.start()..m.end(), m.as_str().to_string()))
+        .collect()
+}
+
+fn tokenize(str: &str) -> Vec<String> {
     let mut res = vec![];
-    for cap in RE.captures_iter(str) {
-        if cap[1].starts_with(";") {
+    for (_, token) in tokens(str) {
+        if token.starts_with(";") {
             continue;
         }
-        res.push(String::from(&cap[1]));
+        res.push(token);
     }
     res
 }
@@ -57,9 +85,83 @@ fn unescape_str(s: &str) -> String {
     .to_string()
 }
 
+// The pattern of a regex literal. Only \" is an escape, the other
+// backslashes are left for the regex.
+fn unescape_regex(s: &str) -> String {
+    let mut res = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('"') => res.push('"'),
+                Some(d) => {
+                    res.push(c);
+                    res.push(d);
+                }
+                None => res.push(c),
+            },
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+fn unescape_edn(s: &str) -> Result<String, MalErr> {
+    let mut res = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('r') => res.push('\r'),
+            Some('b') => res.push('\u{8}'),
+            Some('f') => res.push('\u{c}'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                res.push(read_char(&format!("\\u{}", hex))?);
+            }
+            Some(c @ '"') | Some(c @ '\\') => res.push(c),
+            Some(c) => return Err(ErrString(format!("invalid escape \\{} in string", c))),
+            None => {}
+        }
+    }
+    Ok(res)
+}
+
+// An EDN character like \a, \newline or \u00e9
+fn read_char(token: &str) -> Result<char, MalErr> {
+    let c = match &token[1..] {
+        "newline" => '\n',
+        "space" => ' ',
+        "tab" => '\t',
+        "return" => '\r',
+        "backspace" => '\u{8}',
+        "formfeed" => '\u{c}',
+        name if name.len() == 5 && name.starts_with('u') => {
+            match u32::from_str_radix(&name[1..], 16)
+                .ok()
+                .and_then(std::char::from_u32)
+            {
+                Some(c) => c,
+                None => return Err(ErrString(format!("invalid character {}", token))),
+            }
+        }
+        name if name.chars().count() == 1 => name.chars().next().unwrap_or(' '),
+        _ => return Err(ErrString(format!("invalid character {}", token))),
+    };
+    Ok(c)
+}
+
 fn read_atom(rdr: &mut Reader) -> MalRet {
     lazy_static! {
         static ref INT_RE: Regex = Regex::new(r"^-?[0-9]+$").unwrap();
+        static ref EDN_INT_RE: Regex = Regex::new(r"^[-+]?[0-9]+N?$").unwrap();
+        static ref EDN_FLOAT_RE: Regex =
+            Regex::new(r"^[-+]?[0-9]+(\.[0-9]*)?([eE][-+]?[0-9]+)?M?$").unwrap();
         static ref STR_RE: Regex = Regex::new(r#""(?:\\.|[^\\"])*""#).unwrap();
     }
     let token = rdr.next()?;
@@ -68,8 +170,20 @@ fn read_atom(rdr: &mut Reader) -> MalRet {
         "false" => Ok(Bool(false)),
         "true" => Ok(Bool(true)),
         _ => {
-            if INT_RE.is_match(&token) {
-                Ok(Int(token.parse().unwrap()))
+            if INT_RE.is_match(&token) || (rdr.edn && EDN_INT_RE.is_match(&token)) {
+                match token.trim_end_matches('N').parse() {
+                    Ok(i) => Ok(Int(i)),
+                    Err(_) => error(&format!("integer {} is out of range", token)),
+                }
+            } else if rdr.edn && EDN_FLOAT_RE.is_match(&token) {
+                error(&format!(
+                    "{}: floating point numbers are not supported",
+                    token
+                ))
+            } else if rdr.edn && STR_RE.is_match(&token) {
+                Ok(Str(unescape_edn(&token[1..token.len() - 1])?))
+            } else if rdr.edn && token.starts_with('\\') {
+                Ok(Char(read_char(&token)?))
             } else if STR_RE.is_match(&token) {
                 Ok(Str(unescape_str(&token[1..token.len() - 1])))
             } else if token.starts_with("\"") {
@@ -84,12 +198,31 @@ fn read_atom(rdr: &mut Reader) -> MalRet {
 }
 
 fn read_seq(rdr: &mut Reader, end: &str) -> MalRet {
+    let seq = read_elements(rdr, end)?;
+    match end {
+        ")" => Ok(list!(seq)),
+        "]" => Ok(vector!(seq)),
+        "}" => {
+            if rdr.edn {
+                check_distinct(&seq, 2, "key", "map")?;
+            }
+            hash_map(seq)
+        }
+        _ => error("read_seq unknown end value"),
+    }
+}
+
+// The forms after an opening token up to the closing end
+fn read_elements(rdr: &mut Reader, end: &str) -> Result<Vec<MalVal>, MalErr> {
     let mut seq: Vec<MalVal> = vec![];
     rdr.next()?;
     loop {
+        if rdr.edn {
+            skip_discarded(rdr)?;
+        }
         let token = match rdr.peek() {
             Ok(t) => t,
-            Err(_) => return error(&format!("expected '{}', got EOF", end)),
+            Err(_) => return Err(ErrString(format!("expected '{}', got EOF", end))),
         };
         if token == end {
             break;
@@ -97,15 +230,132 @@ fn read_seq(rdr: &mut Reader, end: &str) -> MalRet {
         seq.push(read_form(rdr)?)
     }
     let _ = rdr.next();
-    match end {
-        ")" => Ok(list!(seq)),
-        "]" => Ok(vector!(seq)),
-        "}" => hash_map(seq),
-        _ => error("read_seq unknown end value"),
+    Ok(seq)
+}
+
+// EDN doesn't allow a map with the same key twice or a set with the same
+// element twice. Every step-th item of items is checked.
+#[allow(clippy::mutable_key_type)]
+fn check_distinct(items: &[MalVal], step: usize, what: &str, coll: &str) -> Result<(), MalErr> {
+    let mut seen = FnvHashSet::default();
+    for item in items.iter().step_by(step) {
+        if !seen.insert(item) {
+            let item = item.pr_str(true);
+            return Err(ErrString(format!(
+                "duplicate {} {} in {}",
+                what, item, coll
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Skip the forms that follow #_
+fn skip_discarded(rdr: &mut Reader) -> Result<(), MalErr> {
+    while rdr.peek().is_ok_and(|t| t == "#_") {
+        rdr.next()?;
+        read_form(rdr)?;
+    }
+    Ok(())
+}
+
+// A tagged literal like #inst "2020-01-01T00:00:00Z". The values of #inst
+// and #uuid are checked, other tags are kept with their form as is.
+fn read_tagged(rdr: &mut Reader, tag: &str) -> MalRet {
+    lazy_static! {
+        static ref INST_RE: Regex = Regex::new(
+            r"^\d{4}(-\d{2}(-\d{2}(T\d{2}:\d{2}(:\d{2}(\.\d+)?)?(Z|[-+]\d{2}:\d{2})?)?)?)?$"
+        )
+        .unwrap();
+        static ref UUID_RE: Regex =
+            Regex::new(r"^[0-9a-fA-F]{8}(-[0-9a-fA-F]{4}){3}-[0-9a-fA-F]{12}$").unwrap();
+    }
+    if !tag.starts_with(|c: char| c.is_alphabetic()) {
+        return error(&format!("invalid tag #{}", tag));
+    }
+    let form = read_form(rdr)?;
+    let valid = match (tag, &form) {
+        ("inst", Str(s)) => INST_RE.is_match(s),
+        ("uuid", Str(s)) => UUID_RE.is_match(s),
+        ("inst", _) | ("uuid", _) => false,
+        _ => true,
+    };
+    if !valid {
+        return error(&format!("invalid #{} {}", tag, form.pr_str(true)));
+    }
+    Ok(Tagged(tag.to_string(), Rc::new(form)))
+}
+
+// A map whose keywords without a namespace get ns, like #:user{:id 1}
+fn read_namespaced_map(rdr: &mut Reader, ns: &str) -> MalRet {
+    if rdr.peek()? != "{" {
+        return error(&format!("expected a map after #:{}", ns));
+    }
+    let kvs: Vec<MalVal> = read_elements(rdr, "}")?
+        .into_iter()
+        .enumerate()
+        .map(|(i, mv)| match mv {
+            Str(ref k) if i % 2 == 0 && mv.keyword_q() => {
+                let name = &k['\u{29e}'.len_utf8()..];
+                if let Some(name) = name.strip_prefix("_/") {
+                    Str(format!("\u{29e}{}", name))
+                } else if name.contains('/') {
+                    mv.clone()
+                } else {
+                    Str(format!("\u{29e}{}/{}", ns, name))
+                }
+            }
+            mv => mv,
+        })
+        .collect();
+    if rdr.edn {
+        check_distinct(&kvs, 2, "key", "map")?;
+    }
+    hash_map(kvs)
+}
+
+// EDN has no reader macros, the dispatch character # starts sets, discarded
+// forms and tagged literals instead
+fn read_edn_form(rdr: &mut Reader) -> MalRet {
+    let token = rdr.peek()?;
+    match &token[..] {
+        "'" | "`" | "~" | "~@" | "^" | "@" => error(&format!("unexpected {} in EDN", token)),
+        "#{" => read_mal_form(rdr),
+        "#_" => {
+            skip_discarded(rdr)?;
+            read_form(rdr)
+        }
+        _ if token.starts_with("#:") => {
+            rdr.next()?;
+            read_namespaced_map(rdr, &token[2..])
+        }
+        _ if token.starts_with('#') => {
+            rdr.next()?;
+            read_tagged(rdr, &token[1..])
+        }
+        _ => read_mal_form(rdr),
     }
 }
 
 fn read_form(rdr: &mut Reader) -> MalRet {
+    if rdr.edn {
+        return read_edn_form(rdr);
+    }
+    read_mal_form(rdr)
+}
+
+fn read_regex(rdr: &mut Reader) -> MalRet {
+    lazy_static! {
+        static ref REGEX_RE: Regex = Regex::new(r#"^#"(?:\\.|[^\\"])*"$"#).unwrap();
+    }
+    let token = rdr.next()?;
+    if !REGEX_RE.is_match(&token) {
+        return error("expected '\"', got EOF");
+    }
+    re_pattern(&unescape_regex(&token[2..token.len() - 1]))
+}
+
+fn read_mal_form(rdr: &mut Reader) -> MalRet {
     let token = rdr.peek()?;
     match &token[..] {
         "'" => {
@@ -139,6 +389,14 @@ fn read_form(rdr: &mut Reader) -> MalRet {
         "[" => read_seq(rdr, "]"),
         "}" => error("unexpected '}'"),
         "{" => read_seq(rdr, "}"),
+        "#{" => {
+            let items = read_elements(rdr, "}")?;
+            if rdr.edn {
+                check_distinct(&items, 1, "element", "set")?;
+            }
+            Ok(set(items))
+        }
+        t if t.starts_with("#\"") => read_regex(rdr),
         _ => read_atom(rdr),
     }
 }
@@ -152,5 +410,53 @@ pub fn read_str(str: String) -> MalRet {
     read_form(&mut Reader {
         pos: 0,
         tokens: tokens,
+        edn: false,
     })
 }
+
+// Read the first form of str as EDN data, nil when there is none. Nothing
+// is evaluated and tags without a reader give Tagged values.
+pub fn read_edn(str: String) -> MalRet {
+    let mut rdr = Reader {
+        pos: 0,
+        tokens: edn_tokens(&str)
+            .into_iter()
+            .map(|(_, t)| t)
+            .filter(|t| !t.starts_with(";"))
+            .collect(),
+        edn: true,
+    };
+    skip_discarded(&mut rdr)?;
+    if rdr.pos == rdr.tokens.len() {
+        return Ok(Nil);
+    }
+    read_form(&mut rdr)
+}
+
+// All the forms of str with the byte range each was read from, stopping
+// at the first one that fails to read
+pub fn read_forms(str: &str) -> Vec<(Range<usize>, MalRet)> {
+    let toks: Vec<(Range<usize>, String)> = tokens(str)
+        .into_iter()
+        .filter(|(_, t)| !t.starts_with(";"))
+        .collect();
+    let mut rdr = Reader {
+        pos: 0,
+        tokens: toks.iter().map(|(_, t)| t.clone()).collect(),
+        edn: false,
+    };
+    let mut forms = vec![];
+    while rdr.pos < toks.len() {
+        let first = rdr.pos;
+        let start = toks[first].0.start;
+        let res = read_form(&mut rdr);
+        // a form that fails on its first token, like ')', still covers it
+        let end = toks[rdr.pos.clamp(first + 1, toks.len()) - 1].0.end;
+        let failed = res.is_err();
+        forms.push((start..end, res));
+        if failed {
+            break;
+        }
+    }
+    forms
+}
@@ -0,0 +1,438 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::time::Instant;
+
+use regex::Regex;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::{Context, Helper};
+
+use crate::core::describe;
+use crate::env::{env_find, env_get, env_keys, Env};
+use crate::printer::with_env;
+use crate::reader::read_str;
+use crate::types::MalErr::ErrString;
+use crate::types::MalVal::{Closure, Func, List, MalFunc, Str, Sym, Vector};
+use crate::types::{MalErr, MalRet, MalVal};
+
+pub const SPECIAL_FORMS: [&str; 16] = [
+    "def!",
+    "let*",
+    "do",
+    "if",
+    "fn*",
+    "quote",
+    "quasiquote",
+    "quasiquoteexpand",
+    "unquote",
+    "splice-unquote",
+    "defmacro!",
+    "macroexpand",
+    "try*",
+    "catch*",
+    "eval",
+    "with-limits",
+];
+
+const DELIMITERS: &str = "()[]{}'`~@^\",;";
+
+// rustyline helper that completes from the live environment
+pub struct MalHelper {
+    env: Env,
+    keywords: RefCell<BTreeSet<String>>,
+}
+
+impl MalHelper {
+    pub fn new(env: &Env) -> MalHelper {
+        MalHelper {
+            env: env.clone(),
+            keywords: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    // Complete against a new environment, e.g. after :reset
+    pub fn set_env(&mut self, env: &Env) {
+        self.env = env.clone();
+    }
+
+    // Remember the keywords in a line of input for later completion
+    pub fn observe(&self, line: &str) {
+        lazy_static! {
+            static ref KW_RE: Regex =
+                Regex::new(r#"(?:^|[\s,(\[{'`~@^])(:[^\s\[\]{}('"`,;)]+)"#).unwrap();
+        }
+        let mut keywords = self.keywords.borrow_mut();
+        for cap in KW_RE.captures_iter(line) {
+            keywords.insert(cap[1].to_string());
+        }
+    }
+
+    fn symbols(&self) -> BTreeSet<String> {
+        let mut syms: BTreeSet<String> = env_keys(&self.env).into_iter().collect();
+        syms.extend(SPECIAL_FORMS.iter().map(|s| s.to_string()));
+        syms
+    }
+
+    pub fn candidates(&self, prefix: &str) -> Vec<String> {
+        if prefix.starts_with(':') {
+            self.keywords
+                .borrow()
+                .iter()
+                .filter(|k| k.starts_with(prefix))
+                .cloned()
+                .collect()
+        } else {
+            self.symbols()
+                .into_iter()
+                .filter(|s| s.starts_with(prefix))
+                .collect()
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<MalVal> {
+        let sym = Sym(name.to_string());
+        env_find(&self.env, name).and_then(|e| env_get(&e, &sym).ok())
+    }
+
+    fn signature(&self, name: &str) -> Option<String> {
+        signature(name, &self.lookup(name)?)
+    }
+}
+
+// A colon-prefixed REPL command. `run` gets the rest of the line and
+// returns the text to print (nothing when empty).
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub run: fn(&mut Repl, &str) -> Result<String, MalErr>,
+}
+
+pub const COMMANDS: [Command; 8] = [
+    Command {
+        name: "doc",
+        usage: ":doc sym",
+        help: "describe what sym is bound to",
+        run: cmd_doc,
+    },
+    Command {
+        name: "env",
+        usage: ":env",
+        help: "list the bindings made in this session",
+        run: cmd_env,
+    },
+    Command {
+        name: "time",
+        usage: ":time expr",
+        help: "evaluate expr and report how long it took",
+        run: cmd_time,
+    },
+    Command {
+        name: "load",
+        usage: ":load file",
+        help: "evaluate the forms in file",
+        run: cmd_load,
+    },
+    Command {
+        name: "pprint",
+        usage: ":pprint [width|off]",
+        help: "pretty print results wider than width (80) columns",
+        run: cmd_pprint,
+    },
+    Command {
+        name: "reset",
+        usage: ":reset",
+        help: "discard all bindings and start from a fresh environment",
+        run: cmd_reset,
+    },
+    Command {
+        name: "quit",
+        usage: ":quit",
+        help: "leave the REPL",
+        run: cmd_quit,
+    },
+    Command {
+        name: "help",
+        usage: ":help",
+        help: "list the REPL commands",
+        run: cmd_help,
+    },
+];
+
+// REPL session state shared by the commands
+pub struct Repl {
+    pub env: Env,
+    pub quit: bool,
+    // results are pretty printed within this width when set
+    pub width: Option<usize>,
+    new_env: Box<dyn Fn() -> Env>,
+    re: fn(&str, &Env) -> MalRet,
+    builtins: BTreeSet<String>,
+    commands: Vec<Command>,
+}
+
+impl Repl {
+    // `new_env` builds the initial environment, and is called again on
+    // :reset. Anything it binds is left out of :env.
+    pub fn new(new_env: Box<dyn Fn() -> Env>, re: fn(&str, &Env) -> MalRet) -> Repl {
+        let env = new_env();
+        Repl {
+            builtins: env_keys(&env).into_iter().collect(),
+            env,
+            quit: false,
+            width: None,
+            new_env,
+            re,
+            commands: COMMANDS.to_vec(),
+        }
+    }
+
+    // Register another command, replacing any with the same name
+    pub fn add_command(&mut self, cmd: Command) {
+        self.commands.retain(|c| c.name != cmd.name);
+        self.commands.push(cmd);
+    }
+
+    pub fn rep(&self, input: &str) -> Result<String, MalErr> {
+        with_env(&self.env, || {
+            let exp = (self.re)(input, &self.env)?;
+            Ok(match self.width {
+                Some(width) => exp.pprint(width),
+                None => exp.pr_str(true),
+            })
+        })
+    }
+
+    // Run `input` if it starts with the name of a command. Other input,
+    // including plain keywords like `:foo`, is left for the evaluator.
+    pub fn command(&mut self, input: &str) -> Option<Result<String, MalErr>> {
+        let line = input.trim_start().strip_prefix(':')?;
+        let (name, args) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line.trim_end(), ""),
+        };
+        let cmd = *self.commands.iter().find(|c| c.name == name)?;
+        Some((cmd.run)(self, args))
+    }
+}
+
+fn cmd_doc(repl: &mut Repl, args: &str) -> Result<String, MalErr> {
+    if args.is_empty() {
+        return Err(ErrString("usage: :doc sym".to_string()));
+    }
+    if SPECIAL_FORMS.contains(&args) {
+        return Ok(format!("{}\n  special form", args));
+    }
+    let val = env_get(&repl.env, &Sym(args.to_string()))?;
+    match val {
+        MalFunc { .. } | Func(..) | Closure(..) => describe(&val, args),
+        _ => Ok(format!("{}\n  {}", args, val.pr_str(true))),
+    }
+}
+
+fn cmd_env(repl: &mut Repl, _args: &str) -> Result<String, MalErr> {
+    let mut keys: Vec<String> = env_keys(&repl.env)
+        .into_iter()
+        .filter(|k| !repl.builtins.contains(k))
+        .collect();
+    keys.sort();
+    let lines: Vec<String> = keys
+        .iter()
+        .map(|k| match env_get(&repl.env, &Sym(k.clone())) {
+            Ok(v @ MalFunc { .. }) => format!("{} {}", k, arglist(&v).unwrap_or_default()),
+            Ok(v) => format!("{} {}", k, v.pr_str(true)),
+            Err(_) => k.clone(),
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+fn cmd_time(repl: &mut Repl, args: &str) -> Result<String, MalErr> {
+    let start = Instant::now();
+    let out = repl.rep(args)?;
+    Ok(format!(
+        "{}\n; elapsed {:.3}ms",
+        out,
+        start.elapsed().as_secs_f64() * 1000.0
+    ))
+}
+
+fn cmd_load(repl: &mut Repl, args: &str) -> Result<String, MalErr> {
+    if args.is_empty() {
+        return Err(ErrString("usage: :load file".to_string()));
+    }
+    repl.rep(&format!(
+        "(load-file {})",
+        Str(args.to_string()).pr_str(true)
+    ))
+}
+
+fn cmd_pprint(repl: &mut Repl, args: &str) -> Result<String, MalErr> {
+    repl.width = match args {
+        "" => Some(80),
+        "off" => None,
+        _ => match args.parse::<usize>() {
+            Ok(width) if width > 0 => Some(width),
+            _ => return Err(ErrString("usage: :pprint [width|off]".to_string())),
+        },
+    };
+    Ok(String::new())
+}
+
+fn cmd_reset(repl: &mut Repl, _args: &str) -> Result<String, MalErr> {
+    repl.env = (repl.new_env)();
+    repl.builtins = env_keys(&repl.env).into_iter().collect();
+    Ok(String::new())
+}
+
+fn cmd_quit(repl: &mut Repl, _args: &str) -> Result<String, MalErr> {
+    repl.quit = true;
+    Ok(String::new())
+}
+
+fn cmd_help(repl: &mut Repl, _args: &str) -> Result<String, MalErr> {
+    let width = repl
+        .commands
+        .iter()
+        .map(|c| c.usage.len())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<String> = repl
+        .commands
+        .iter()
+        .map(|c| format!("{:width$}  {}", c.usage, c.help, width = width))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+// "(name a b)" for functions whose parameters are known
+fn signature(name: &str, f: &MalVal) -> Option<String> {
+    let params = arglist(f)?;
+    match params.trim_start_matches('(').trim_end_matches(')') {
+        "" => Some(format!("({})", name)),
+        p => Some(format!("({} {})", name, p)),
+    }
+}
+
+// The printed parameter list of a function value
+pub fn arglist(f: &MalVal) -> Option<String> {
+    match f {
+        MalFunc { params, .. } => match **params {
+            List(ref p, _) | Vector(ref p, _) => Some(format!(
+                "({})",
+                p.iter()
+                    .map(|x| x.pr_str(true))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )),
+            _ => None,
+        },
+        Closure(c, _) => c.doc.map(|(params, _)| params.to_string()),
+        _ => None,
+    }
+}
+
+// True when the reader ran out of input inside a form. It reports
+// unbalanced delimiters and unterminated strings as "expected 'x', got EOF".
+pub fn incomplete(input: &str) -> bool {
+    match read_str(input.to_string()) {
+        Err(ErrString(ref e)) => e.starts_with("expected '") && e.ends_with("', got EOF"),
+        _ => false,
+    }
+}
+
+fn word_start(line: &str, pos: usize) -> usize {
+    match line[..pos].rfind(|c: char| c.is_whitespace() || DELIMITERS.contains(c)) {
+        Some(i) => i + line[i..].chars().next().map_or(1, |c| c.len_utf8()),
+        None => 0,
+    }
+}
+
+// The symbol at the head of the innermost unclosed list before pos
+fn call_head(line: &str, pos: usize) -> Option<&str> {
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut open = None;
+    let bytes = &line.as_bytes()[..pos];
+    for i in (0..bytes.len()).rev() {
+        let c = bytes[i];
+        if c == b'"' && (i == 0 || bytes[i - 1] != b'\\') {
+            in_str = !in_str;
+        } else if in_str {
+            continue;
+        } else if c == b')' || c == b']' || c == b'}' {
+            depth += 1;
+        } else if c == b'(' || c == b'[' || c == b'{' {
+            if depth == 0 {
+                if c == b'(' {
+                    open = Some(i + 1);
+                }
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let start = open?;
+    let rest = &line[start..pos];
+    let end = rest
+        .find(|c: char| c.is_whitespace() || DELIMITERS.contains(c))
+        .unwrap_or(rest.len());
+    match &rest[..end] {
+        "" => None,
+        head => Some(head),
+    }
+}
+
+impl Completer for MalHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = word_start(line, pos);
+        let pairs = self
+            .candidates(&line[start..pos])
+            .into_iter()
+            .map(|name| Pair {
+                display: match self.signature(&name) {
+                    Some(sig) => sig,
+                    None => name.clone(),
+                },
+                replacement: name,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for MalHelper {
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let prefix = &line[word_start(line, pos)..pos];
+        let (rest, name) = match self.candidates(prefix).as_slice() {
+            [only] if !prefix.is_empty() => (only[prefix.len()..].to_string(), only.clone()),
+            _ => (String::new(), call_head(line, pos)?.to_string()),
+        };
+        match self.signature(&name) {
+            Some(sig) => Some(format!("{} ; {}", rest, sig)),
+            None if !rest.is_empty() => Some(rest),
+            None => None,
+        }
+    }
+}
+
+impl Highlighter for MalHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
+
+impl Helper for MalHelper {}
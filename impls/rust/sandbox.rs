@@ -0,0 +1,173 @@
+use std::cell::RefCell;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+use crate::types::MalErr;
+use crate::types::MalErr::ErrString;
+
+// Where a script may read or write files
+#[derive(Debug, Clone)]
+pub enum Access {
+    Denied,
+    Anywhere,
+    // Only within these directories
+    Roots(Vec<PathBuf>),
+}
+
+// The host resources that the I/O builtins may use. Everything is allowed
+// unless an interpreter is given fewer capabilities.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub read: Access,
+    pub write: Access,
+    pub stdin: bool,
+    pub clock: bool,
+    // Running programs, exiting and the environment variables
+    pub process: bool,
+}
+
+impl Capabilities {
+    pub fn all() -> Capabilities {
+        Capabilities {
+            read: Access::Anywhere,
+            write: Access::Anywhere,
+            stdin: true,
+            clock: true,
+            process: true,
+        }
+    }
+
+    // Pure computation, for untrusted scripts
+    pub fn none() -> Capabilities {
+        Capabilities {
+            read: Access::Denied,
+            write: Access::Denied,
+            stdin: false,
+            clock: false,
+            process: false,
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Capabilities {
+        Capabilities::all()
+    }
+}
+
+// Active capabilities, innermost last. A call must be allowed by all of
+// them, so a nested scope can only take capabilities away.
+thread_local! {
+    static SCOPES: RefCell<Vec<Capabilities>> = const { RefCell::new(Vec::new()) };
+}
+
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        SCOPES.with(|s| s.borrow_mut().pop());
+    }
+}
+
+// Run `f` with `caps`, on top of any that are already active
+pub fn with_capabilities<T, F: FnOnce() -> T>(caps: Capabilities, f: F) -> T {
+    SCOPES.with(|s| s.borrow_mut().push(caps));
+    let _guard = ScopeGuard;
+    f()
+}
+
+fn allowed<F: FnMut(&Capabilities) -> bool>(test: F) -> bool {
+    SCOPES.with(|s| s.borrow().iter().all(test))
+}
+
+fn denied(name: &str, what: &str) -> MalErr {
+    ErrString(format!("{}: permission denied: cannot {}", name, what))
+}
+
+// path made absolute, with the symlinks and .. of the part that exists
+// resolved by the OS and the rest normalized by hand, so that it can be
+// compared with the roots
+fn resolve(path: &Path) -> PathBuf {
+    let abs = match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+    let mut base = abs.as_path();
+    let mut rest = vec![];
+    let mut res = loop {
+        if let Ok(p) = base.canonicalize() {
+            break p;
+        }
+        match (base.parent(), base.components().next_back()) {
+            (Some(parent), Some(last)) => {
+                rest.push(last);
+                base = parent;
+            }
+            _ => break PathBuf::new(),
+        }
+    };
+    for c in rest.into_iter().rev() {
+        match c {
+            Component::ParentDir => {
+                res.pop();
+            }
+            Component::Normal(n) => res.push(n),
+            _ => {}
+        }
+    }
+    res
+}
+
+// Check that every active scope lets the builtin name verb path. path is
+// only resolved when there are roots to compare it with.
+fn check_path(
+    name: &str,
+    verb: &str,
+    path: &str,
+    access: fn(&Capabilities) -> &Access,
+) -> Result<(), MalErr> {
+    let mut resolved = None;
+    let ok = allowed(|c| match access(c) {
+        Access::Denied => false,
+        Access::Anywhere => true,
+        Access::Roots(roots) => {
+            let path = resolved.get_or_insert_with(|| resolve(Path::new(path)));
+            roots.iter().any(|r| path.starts_with(resolve(r)))
+        }
+    });
+    match ok {
+        true => Ok(()),
+        false => Err(denied(name, &format!("{} {}", verb, path))),
+    }
+}
+
+// The builtin name may read the file or directory path
+pub fn check_read(name: &str, path: &str) -> Result<(), MalErr> {
+    check_path(name, "read", path, |c| &c.read)
+}
+
+// The builtin name may create, change or delete the file or directory path
+pub fn check_write(name: &str, path: &str) -> Result<(), MalErr> {
+    check_path(name, "write", path, |c| &c.write)
+}
+
+pub fn check_stdin(name: &str) -> Result<(), MalErr> {
+    match allowed(|c| c.stdin) {
+        true => Ok(()),
+        false => Err(denied(name, "read stdin")),
+    }
+}
+
+pub fn check_clock(name: &str) -> Result<(), MalErr> {
+    match allowed(|c| c.clock) {
+        true => Ok(()),
+        false => Err(denied(name, "read the clock")),
+    }
+}
+
+pub fn check_process(name: &str) -> Result<(), MalErr> {
+    match allowed(|c| c.process) {
+        true => Ok(()),
+        false => Err(denied(name, "access the host process")),
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::bencode::{self, Bencode};
+use crate::budget;
+use crate::env::{env_sets, Env};
+use crate::printer::{capture, with_env};
+use crate::reader::read_forms;
+use crate::repl::MalHelper;
+use crate::types::MalErr::ErrMalVal;
+use crate::types::MalVal::{Nil, Str};
+use crate::types::{format_error, MalRet, MalVal};
+
+type Writer = Arc<Mutex<Box<dyn Write + Send>>>;
+type Message = BTreeMap<String, Bencode>;
+
+enum Event {
+    Open(u64, Writer),
+    Request(u64, Message),
+    Close(u64),
+}
+
+// The session being evaluated, 0 when idle
+static RUNNING: AtomicU64 = AtomicU64::new(0);
+
+// The state of one connection. Definitions go to the shared environment,
+// only the last results (*1, *2, *3) and exception (*e) are per session.
+struct Session {
+    writer: Writer,
+    results: [MalVal; 3],
+    exception: MalVal,
+}
+
+// Serve nREPL-style requests on `addr`, either host:port for TCP or
+// unix:path for a Unix domain socket. Each connection is read on its own
+// thread, but everything is evaluated on this one, in `env`.
+pub fn serve(addr: &str, env: &Env, re: fn(&str, &Env) -> MalRet) -> io::Result<()> {
+    let (tx, rx) = channel();
+    listen(addr, tx)?;
+    let helper = MalHelper::new(env);
+    let mut sessions: HashMap<u64, Session> = HashMap::new();
+    for event in rx {
+        match event {
+            Event::Open(id, writer) => {
+                let session = Session {
+                    writer,
+                    results: [Nil, Nil, Nil],
+                    exception: Nil,
+                };
+                sessions.insert(id, session);
+            }
+            Event::Request(id, req) => {
+                if let Some(session) = sessions.get_mut(&id) {
+                    let resp = handle(session, id, &req, env, re, &helper);
+                    respond(&session.writer, id, &req, resp);
+                }
+            }
+            Event::Close(id) => {
+                sessions.remove(&id);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn listen(addr: &str, tx: Sender<Event>) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        if let Some(path) = addr.strip_prefix("unix:") {
+            let listener = UnixListener::bind(path)?;
+            eprintln!("nREPL server listening on {}", addr);
+            thread::spawn(move || accept(listener.incoming(), UnixStream::try_clone, tx));
+            return Ok(());
+        }
+    }
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("nREPL server listening on {}", listener.local_addr()?);
+    thread::spawn(move || accept(listener.incoming(), TcpStream::try_clone, tx));
+    Ok(())
+}
+
+fn accept<S, I>(incoming: I, try_clone: fn(&S) -> io::Result<S>, tx: Sender<Event>)
+where
+    S: Read + Write + Send + 'static,
+    I: Iterator<Item = io::Result<S>>,
+{
+    for (id, stream) in (1..).zip(incoming) {
+        let (stream, writer) = match stream.and_then(|s| try_clone(&s).map(|w| (s, w))) {
+            Ok(pair) => pair,
+            Err(_) => continue,
+        };
+        let writer: Writer = Arc::new(Mutex::new(Box::new(writer)));
+        if tx.send(Event::Open(id, writer.clone())).is_err() {
+            return;
+        }
+        let tx = tx.clone();
+        thread::spawn(move || read_requests(id, stream, writer, tx));
+    }
+}
+
+// Forward the requests of a connection to the evaluating thread. Interrupts
+// are handled here, since that thread is busy with what they interrupt.
+fn read_requests<S: Read>(id: u64, stream: S, writer: Writer, tx: Sender<Event>) {
+    let mut reader = BufReader::new(stream);
+    while let Ok(Some(msg)) = bencode::read(&mut reader) {
+        let req = match msg {
+            Bencode::Dict(req) => req,
+            _ => continue,
+        };
+        if field(&req, "op") == Some("interrupt") {
+            let status = if RUNNING.load(Ordering::SeqCst) == id {
+                budget::interrupt();
+                "interrupted"
+            } else {
+                "session-idle"
+            };
+            respond(&writer, id, &req, status_only(&["done", status]));
+        } else if tx.send(Event::Request(id, req)).is_err() {
+            break;
+        }
+    }
+    let _ = tx.send(Event::Close(id));
+}
+
+fn field<'a>(req: &'a Message, key: &str) -> Option<&'a str> {
+    match req.get(key) {
+        Some(Bencode::Str(s)) => Some(s),
+        _ => None,
+    }
+}
+
+fn status(statuses: &[&str]) -> Bencode {
+    Bencode::List(
+        statuses
+            .iter()
+            .map(|s| Bencode::Str(s.to_string()))
+            .collect(),
+    )
+}
+
+fn status_only(statuses: &[&str]) -> Message {
+    let mut resp = Message::new();
+    resp.insert("status".to_string(), status(statuses));
+    resp
+}
+
+fn respond(writer: &Writer, id: u64, req: &Message, mut resp: Message) {
+    resp.insert("session".to_string(), Bencode::Str(id.to_string()));
+    if let Some(req_id) = req.get("id") {
+        resp.insert("id".to_string(), req_id.clone());
+    }
+    if let Ok(mut w) = writer.lock() {
+        let _ = bencode::write(&mut **w, &Bencode::Dict(resp));
+    }
+}
+
+fn handle(
+    session: &mut Session,
+    id: u64,
+    req: &Message,
+    env: &Env,
+    re: fn(&str, &Env) -> MalRet,
+    helper: &MalHelper,
+) -> Message {
+    match field(req, "op").unwrap_or("") {
+        "eval" => {
+            let code = field(req, "code").unwrap_or("");
+            helper.observe(code);
+            evaluate(session, id, code, env, re)
+        }
+        "load-file" => match (field(req, "file"), field(req, "file-path")) {
+            (Some(contents), _) => evaluate(session, id, contents, env, re),
+            (None, Some(path)) => {
+                let code = format!("(load-file {})", Str(path.to_string()).pr_str(true));
+                evaluate(session, id, &code, env, re)
+            }
+            (None, None) => status_only(&["done", "error", "no-file"]),
+        },
+        "complete" => {
+            let prefix = field(req, "prefix").or_else(|| field(req, "symbol"));
+            let completions = helper
+                .candidates(prefix.unwrap_or(""))
+                .into_iter()
+                .map(|c| {
+                    let mut entry = Message::new();
+                    entry.insert("candidate".to_string(), Bencode::Str(c));
+                    Bencode::Dict(entry)
+                })
+                .collect();
+            let mut resp = status_only(&["done"]);
+            resp.insert("completions".to_string(), Bencode::List(completions));
+            resp
+        }
+        "describe" => {
+            let ops = ["eval", "load-file", "complete", "interrupt", "describe"]
+                .iter()
+                .map(|op| (op.to_string(), Bencode::Dict(Message::new())))
+                .collect();
+            let mut resp = status_only(&["done"]);
+            resp.insert("ops".to_string(), Bencode::Dict(ops));
+            resp
+        }
+        _ => status_only(&["done", "error", "unknown-op"]),
+    }
+}
+
+// Evaluate the forms of code in turn, to the value of the last one. A form
+// that can't be read is an error, not the end of the code.
+fn re_forms(code: &str, env: &Env, re: fn(&str, &Env) -> MalRet) -> MalRet {
+    read_forms(code)
+        .into_iter()
+        .try_fold(Nil, |_, (span, form)| {
+            form.and_then(|_| re(&code[span], env))
+        })
+}
+
+fn evaluate(
+    session: &mut Session,
+    id: u64,
+    code: &str,
+    env: &Env,
+    re: fn(&str, &Env) -> MalRet,
+) -> Message {
+    for (name, val) in ["*1", "*2", "*3"].iter().zip(session.results.iter()) {
+        env_sets(env, name, val.clone());
+    }
+    env_sets(env, "*e", session.exception.clone());
+
+    budget::clear_interrupt();
+    RUNNING.store(id, Ordering::SeqCst);
+    let (res, out) = capture(|| re_forms(code, env, re));
+    RUNNING.store(0, Ordering::SeqCst);
+
+    let mut resp = Message::new();
+    if !out.is_empty() {
+        resp.insert("out".to_string(), Bencode::Str(out));
+    }
+    match res {
+        Ok(val) => {
+            let value = with_env(env, || val.pr_str(true));
+            resp.insert("value".to_string(), Bencode::Str(value));
+            session.results.rotate_right(1);
+            session.results[0] = val;
+            resp.insert("status".to_string(), status(&["done"]));
+        }
+        Err(e) => {
+            let exc = match e {
+                ErrMalVal(ref mv) => Some(mv.clone()),
+                _ => None,
+            };
+            let msg = format_error(e);
+            session.exception = exc.unwrap_or_else(|| Str(msg.clone()));
+            resp.insert("err".to_string(), Bencode::Str(msg));
+            resp.insert("status".to_string(), status(&["done", "error"]));
+        }
+    }
+    resp
+}
@@ -12,7 +12,9 @@ use rustyline::Editor;
 #[allow(dead_code)]
 mod types;
 use crate::types::format_error;
+#[allow(dead_code)]
 mod printer;
+#[allow(dead_code)]
 mod reader;
 // TODO: figure out a way to avoid including env
 #[allow(dead_code)]
@@ -18,7 +18,9 @@ mod types;
 use crate::types::MalErr::ErrString;
 use crate::types::MalVal::{Hash, Int, List, Nil, Sym, Vector};
 use crate::types::{error, format_error, func, MalArgs, MalErr, MalRet, MalVal};
+#[allow(dead_code)]
 mod printer;
+#[allow(dead_code)]
 mod reader;
 // TODO: figure out a way to avoid including env
 #[allow(dead_code)]
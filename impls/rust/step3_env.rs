@@ -18,8 +18,11 @@ use rustyline::Editor;
 mod types;
 use crate::types::MalVal::{Hash, Int, List, Nil, Sym, Vector};
 use crate::types::{error, format_error, func, MalArgs, MalErr, MalRet, MalVal};
+#[allow(dead_code)]
 mod env;
+#[allow(dead_code)]
 mod printer;
+#[allow(dead_code)]
 mod reader;
 use crate::env::{env_get, env_new, env_set, env_sets, Env};
 
@@ -3,27 +3,20 @@ use std::rc::Rc;
 use fnv::FnvHashMap;
 use itertools::Itertools;
 
-#[macro_use]
-extern crate lazy_static;
 extern crate fnv;
 extern crate itertools;
-extern crate regex;
 
 extern crate rustyline;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
 #[macro_use]
-mod types;
-use crate::types::MalErr::{ErrMalVal, ErrString};
-use crate::types::MalVal::{Bool, Func, Hash, List, MalFunc, Nil, Str, Sym, Vector};
-use crate::types::{error, format_error, MalArgs, MalErr, MalRet, MalVal};
-mod env;
-mod printer;
-mod reader;
-use crate::env::{env_bind, env_find, env_get, env_new, env_set, env_sets, Env};
-#[macro_use]
-mod core;
+extern crate mal;
+use mal::types::MalErr::{ErrInterrupted, ErrLimit, ErrMalVal, ErrString};
+use mal::types::MalVal::{Bool, Closure, Func, Hash, List, MalFunc, Nil, Str, Sym, Vector};
+use mal::types::{error, format_error, MalArgs, MalErr, MalRet, MalVal};
+use mal::env::{env_bind, env_find, env_get, env_new, env_set, env_sets, Env};
+use mal::{core, printer, reader};
 
 // read
 fn read(str: &str) -> MalRet {
@@ -221,6 +214,8 @@ fn eval(mut ast: MalVal, mut env: Env) -> MalRet {
                             let exc = match e {
                                 ErrMalVal(mv) => mv.clone(),
                                 ErrString(s) => Str(s.to_string()),
+                                ErrLimit(l) => Str(l.to_string()),
+                                ErrInterrupted => Str("interrupted".to_string()),
                             };
                             match l[2].clone() {
                                 List(c, _) => {
@@ -283,7 +278,7 @@ fn eval(mut ast: MalVal, mut env: Env) -> MalRet {
                             let ref f = el[0].clone();
                             let args = el[1..].to_vec();
                             match f {
-                                Func(_, _) => f.apply(args),
+                                Func(_, _) | Closure(_, _) => f.apply(args),
                                 MalFunc {
                                     ast: mast,
                                     env: menv,
@@ -318,9 +313,11 @@ fn print(ast: &MalVal) -> String {
 }
 
 fn rep(str: &str, env: &Env) -> Result<String, MalErr> {
-    let ast = read(str)?;
-    let exp = eval(ast, env.clone())?;
-    Ok(print(&exp))
+    printer::with_env(env, || {
+        let ast = read(str)?;
+        let exp = eval(ast, env.clone())?;
+        Ok(print(&exp))
+    })
 }
 
 fn main() {
@@ -338,7 +335,10 @@ fn main() {
     for (k, v) in core::ns() {
         env_sets(&repl_env, k, v);
     }
-    env_sets(&repl_env, "*ARGV*", list!(args.map(Str).collect()));
+    core::install(&repl_env);
+    let argv = list!(args.map(Str).collect());
+    env_sets(&repl_env, "*ARGV*", argv.clone());
+    env_sets(&repl_env, "*command-line-args*", argv);
 
     // core.mal: defined using the language itself
     let _ = rep("(def! not (fn* (a) (if a false true)))", &repl_env);
@@ -0,0 +1,223 @@
+use std::rc::Rc;
+
+use regex::{Captures, Regex};
+
+use crate::types::MalErr::ErrString;
+use crate::types::MalVal::{Int, List, Nil, Set, Str, Vector};
+use crate::types::{arg, compile_regex, error, MalArgs, MalErr, MalRet, MalVal};
+
+// Strings are indexed by Unicode scalar, so these take and return char
+// indices rather than byte offsets
+
+// The byte offset of the char at index i of s, the length of s for the
+// index right past its end
+fn byte_offset(s: &str, i: i64) -> Option<usize> {
+    if i < 0 {
+        return None;
+    }
+    s.char_indices()
+        .map(|(b, _)| b)
+        .chain(Some(s.len()))
+        .nth(i as usize)
+}
+
+pub fn subs(a: MalArgs) -> MalRet {
+    let s: String = arg("subs", &a, 0)?;
+    let start: i64 = arg("subs", &a, 1)?;
+    let end: i64 = match a.get(2) {
+        Some(_) => arg("subs", &a, 2)?,
+        None => s.chars().count() as i64,
+    };
+    match (byte_offset(&s, start), byte_offset(&s, end)) {
+        (Some(i), Some(j)) if i <= j => Ok(Str(s[i..j].to_string())),
+        _ => error(&format!(
+            "subs: range {}..{} is out of bounds for a string of length {}",
+            start,
+            end,
+            s.chars().count()
+        )),
+    }
+}
+
+// A string separator matches itself and nothing else
+fn separator(name: &str, sep: &MalVal) -> Result<Regex, MalErr> {
+    match sep {
+        MalVal::Regex(re) => Ok(re.clone()),
+        Str(s) if !sep.keyword_q() => compile_regex(&regex::escape(s)),
+        _ => Err(ErrString(format!(
+            "{}: separator must be a string or a regex",
+            name
+        ))),
+    }
+}
+
+// Like Java's String.split, the trailing empty strings are dropped unless
+// a limit is given, and a separator matching nothing at the start doesn't
+// make an empty first part, so that "" splits into characters
+pub fn split(a: MalArgs) -> MalRet {
+    let s: String = arg("split", &a, 0)?;
+    let re = separator("split", &a[1])?;
+    if s.is_empty() {
+        return Ok(vector!(vec![Str(s)]));
+    }
+    let skip = match re.find(&s) {
+        Some(m) if m.end() == 0 => 1,
+        _ => 0,
+    };
+    let parts: Vec<&str> = match a.get(2) {
+        Some(_) => match arg::<i64>("split", &a, 2)? {
+            n if n > 0 => re.splitn(&s, n as usize + skip).skip(skip).collect(),
+            _ => re.split(&s).skip(skip).collect(),
+        },
+        None => {
+            let mut parts: Vec<&str> = re.split(&s).skip(skip).collect();
+            while parts.last() == Some(&"") {
+                parts.pop();
+            }
+            parts
+        }
+    };
+    Ok(vector!(parts
+        .into_iter()
+        .map(|p| Str(p.to_string()))
+        .collect()))
+}
+
+pub fn join(a: MalArgs) -> MalRet {
+    let (sep, coll) = match a.len() {
+        1 => (String::new(), &a[0]),
+        2 => (arg("join", &a, 0)?, &a[1]),
+        n => return error(&format!("join: expected 1 or 2 arguments, got {}", n)),
+    };
+    let strs: Vec<String> = match coll {
+        List(v, _) | Vector(v, _) => v.iter().map(|mv| mv.pr_str(false)).collect(),
+        Set(s, _) => s.iter().map(|mv| mv.pr_str(false)).collect(),
+        Nil => vec![],
+        _ => return error("join: expected a list, vector or set"),
+    };
+    Ok(Str(strs.join(&sep)))
+}
+
+// The char index of the first occurrence of value in s at or after from,
+// or nil
+pub fn index_of(a: MalArgs) -> MalRet {
+    let s: String = arg("index-of", &a, 0)?;
+    let value: String = arg("index-of", &a, 1)?;
+    let from: i64 = match a.get(2) {
+        Some(_) => arg("index-of", &a, 2)?,
+        None => 0,
+    };
+    let start = match byte_offset(&s, from.max(0)) {
+        Some(start) => start,
+        None => return Ok(Nil),
+    };
+    match s[start..].find(&value) {
+        Some(i) => Ok(Int(s[..start + i].chars().count() as i64)),
+        None => Ok(Nil),
+    }
+}
+
+// The widest a format directive may pad to, so that a bogus width is an
+// error rather than a huge allocation
+const MAX_WIDTH: usize = 1 << 20;
+
+// Pads s to width chars: on the right when left justified, otherwise on
+// the left, with zeros after the sign when zero padded
+fn pad(s: String, flags: &str, width: usize) -> String {
+    let len = s.chars().count();
+    if len >= width {
+        return s;
+    }
+    let fill = width - len;
+    if flags.contains('-') {
+        format!("{}{}", s, " ".repeat(fill))
+    } else if flags.contains('0') {
+        let digits = s.trim_start_matches(['-', '+']);
+        let sign = &s[..s.len() - digits.len()];
+        format!("{}{}{}", sign, "0".repeat(fill), digits)
+    } else {
+        format!("{}{}", " ".repeat(fill), s)
+    }
+}
+
+// The text for the directive in caps, consuming the arguments it uses
+fn directive<'a, I>(caps: &Captures, args: &mut I) -> Result<String, MalErr>
+where
+    I: Iterator<Item = &'a MalVal>,
+{
+    let flags = &caps[1];
+    let conversion = &caps[4];
+    match conversion {
+        "%" => return Ok("%".to_string()),
+        "n" => return Ok("\n".to_string()),
+        "" => {
+            return Err(ErrString(
+                "format: incomplete directive at the end of the format string".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    let width = match &caps[2] {
+        "" => 0,
+        w => match w.parse() {
+            Ok(width) if width <= MAX_WIDTH => width,
+            _ => {
+                return Err(ErrString(format!(
+                    "format: width {} is larger than {}",
+                    w, MAX_WIDTH
+                )))
+            }
+        },
+    };
+    let precision = caps.get(3).and_then(|p| p.as_str().parse().ok());
+    let mv = args
+        .next()
+        .ok_or_else(|| ErrString(format!("format: no argument left for %{}", conversion)))?;
+    let int = |mv: &MalVal| match mv {
+        Int(i) => Ok(*i),
+        _ => Err(ErrString(format!(
+            "format: %{} expects an int, got {}",
+            conversion,
+            mv.pr_str(true)
+        ))),
+    };
+    let s = match conversion {
+        "s" => match precision {
+            Some(p) => mv.pr_str(false).chars().take(p).collect(),
+            None => mv.pr_str(false),
+        },
+        "d" => match int(mv)? {
+            i if i >= 0 && flags.contains('+') => format!("+{}", i),
+            i => i.to_string(),
+        },
+        "x" => format!("{:x}", int(mv)?),
+        "X" => format!("{:X}", int(mv)?),
+        "o" => format!("{:o}", int(mv)?),
+        "b" => format!("{:b}", int(mv)?),
+        c => return Err(ErrString(format!("format: unknown conversion %{}", c))),
+    };
+    Ok(pad(s, flags, width))
+}
+
+// printf-style formatting. A directive is %[flags][width][.precision]c,
+// where the flags are - to left justify, 0 to pad with zeros and + to
+// always sign a %d, and c is one of s (a value as str prints it), d (an
+// int), x or X (hexadecimal), o (octal), b (binary), n (a newline) or %
+// (a percent sign).
+pub fn format(a: MalArgs) -> MalRet {
+    lazy_static! {
+        static ref DIRECTIVE_RE: Regex = Regex::new(r"%([-0+]*)(\d*)(?:\.(\d+))?(.?)").unwrap();
+    }
+    let fmt: String = arg("format", &a, 0)?;
+    let mut args = a[1..].iter();
+    let mut res = String::new();
+    let mut last = 0;
+    for caps in DIRECTIVE_RE.captures_iter(&fmt) {
+        let (start, end) = caps.get(0).map_or((0, 0), |m| (m.start(), m.end()));
+        res.push_str(&fmt[last..start]);
+        res.push_str(&directive(&caps, &mut args)?);
+        last = end;
+    }
+    res.push_str(&fmt[last..]);
+    Ok(Str(res))
+}
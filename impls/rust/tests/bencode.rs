@@ -0,0 +1,95 @@
+// Encode and decode the messages of the nREPL-style server
+
+extern crate mal;
+
+use mal::bencode::{self, Bencode, MAX_DEPTH, MAX_STRING_LEN};
+use std::collections::BTreeMap;
+
+fn decode(bytes: &[u8]) -> Result<Option<Bencode>, String> {
+    let mut r = bytes;
+    bencode::read(&mut r).map_err(|e| e.to_string())
+}
+
+fn encode(v: &Bencode) -> Vec<u8> {
+    let mut out = Vec::new();
+    bencode::encode(v, &mut out);
+    out
+}
+
+#[test]
+fn round_trip() {
+    let mut dict = BTreeMap::new();
+    dict.insert("op".to_string(), Bencode::Str("eval".to_string()));
+    dict.insert("code".to_string(), Bencode::Str("(str \"é\")".to_string()));
+    dict.insert(
+        "ids".to_string(),
+        Bencode::List(vec![
+            Bencode::Int(-3),
+            Bencode::Int(0),
+            Bencode::List(vec![]),
+        ]),
+    );
+    dict.insert("empty".to_string(), Bencode::Dict(BTreeMap::new()));
+    let msg = Bencode::Dict(dict);
+    let bytes = encode(&msg);
+    assert_eq!(decode(&bytes), Ok(Some(msg)));
+
+    assert_eq!(encode(&Bencode::Str("é".to_string())), b"2:\xc3\xa9");
+    assert_eq!(
+        encode(&Bencode::List(vec![
+            Bencode::Int(42),
+            Bencode::Str(String::new())
+        ])),
+        b"li42e0:e"
+    );
+    assert_eq!(decode(b""), Ok(None));
+
+    // messages follow each other on a stream
+    let mut r: &[u8] = b"i1e3:abc";
+    assert_eq!(bencode::read(&mut r).unwrap(), Some(Bencode::Int(1)));
+    assert_eq!(
+        bencode::read(&mut r).unwrap(),
+        Some(Bencode::Str("abc".to_string()))
+    );
+    assert_eq!(bencode::read(&mut r).unwrap(), None);
+}
+
+#[test]
+fn malformed() {
+    let cases: [(&[u8], &str); 9] = [
+        (b"i12", "unexpected end of message"),
+        (b"i1x2e", "bad integer"),
+        (b"5:ab", "unexpected end of message"),
+        (b"3x:abc", "bad string length"),
+        (b"li1e", "unexpected end of message"),
+        (b"d3:key", "unexpected end of message"),
+        (b"di1ei2ee", "dictionary key is not a string"),
+        (b"x", "unexpected byte"),
+        (b"99999999999999999999:", "bad string length"),
+    ];
+    for (bytes, err) in cases.iter() {
+        assert_eq!(decode(bytes), Err(err.to_string()), "{:?}", bytes);
+    }
+
+    // a huge length is refused before anything is allocated for it
+    let oversized = format!("{}:abc", MAX_STRING_LEN + 1);
+    assert_eq!(
+        decode(oversized.as_bytes()),
+        Err("string too long".to_string())
+    );
+    assert_eq!(decode(b"99999999999:"), Err("string too long".to_string()));
+
+    // so are endless numbers and nesting
+    let digits = vec![b'1'; 100];
+    assert_eq!(
+        decode(&[&b"i"[..], &digits].concat()),
+        Err("number too long".to_string())
+    );
+    assert_eq!(decode(&digits), Err("number too long".to_string()));
+    let nested = |depth: usize| [vec![b'l'; depth], vec![b'e'; depth]].concat();
+    assert!(decode(&nested(MAX_DEPTH)).is_ok());
+    assert_eq!(
+        decode(&nested(MAX_DEPTH + 1)),
+        Err("nesting too deep".to_string())
+    );
+}
@@ -0,0 +1,301 @@
+// Embed the interpreter through the library API
+
+extern crate mal;
+
+use mal::core::describe;
+use mal::types::format_error;
+use mal::MalVal::{Int, Nil, Str};
+use mal::{Access, Capabilities, Interpreter, MalArgs, MalRet};
+use std::cell::Cell;
+use std::fs;
+use std::rc::Rc;
+
+fn eval(interp: &Interpreter, src: &str) -> String {
+    match interp.eval_str(src) {
+        Ok(val) => val.pr_str(true),
+        Err(e) => format!("Error: {}", format_error(e)),
+    }
+}
+
+#[test]
+fn eval_str() {
+    let interp = Interpreter::new();
+    assert_eq!(eval(&interp, "(+ 1 2)"), "3");
+    assert_eq!(
+        eval(&interp, "(def! sq (fn* (x) (* x x))) ; square\n(sq 7)"),
+        "49"
+    );
+    assert_eq!(eval(&interp, "(not (cond false 1 :else false))"), "true");
+    assert_eq!(eval(&interp, "*host-language*"), "\"rust\"");
+    assert_eq!(eval(&interp, ";; nothing"), "nil");
+    assert_eq!(eval(&interp, "(undefined)"), "Error: 'undefined' not found");
+    assert_eq!(eval(&interp, "(+ 1"), "Error: expected ')', got EOF");
+    assert_eq!(eval(&interp, ") (+ 1 2)"), "Error: unexpected ')'");
+
+    // each interpreter has its own globals
+    assert_eq!(eval(&Interpreter::new(), "(sq 7)"), "Error: 'sq' not found");
+}
+
+#[test]
+fn globals() {
+    let interp = Interpreter::with_args(&["a".to_string()]);
+    assert_eq!(eval(&interp, "*ARGV*"), "(\"a\")");
+    interp.set("answer", Int(42));
+    assert_eq!(eval(&interp, "(+ answer 1)"), "43");
+    interp.eval_str("(def! greeting \"hi\")").unwrap();
+    assert_eq!(interp.get("greeting"), Some(Str("hi".to_string())));
+    assert_eq!(interp.get("missing"), None);
+}
+
+fn count_args(args: MalArgs) -> MalRet {
+    Ok(Int(args.len() as i64))
+}
+
+#[test]
+fn register() {
+    let interp = Interpreter::new();
+    interp.register("count-args", count_args);
+    assert_eq!(eval(&interp, "(count-args 1 2 3)"), "3");
+    assert_eq!(eval(&interp, "(apply count-args [])"), "0");
+    assert_eq!(eval(&interp, "(fn? count-args)"), "true");
+    assert_eq!(eval(&interp, "count-args"), "#<fn count-args>");
+
+    // closures can keep host state
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    interp.register("next-id", move |_| {
+        counter.set(counter.get() + 1);
+        Ok(Int(counter.get()))
+    });
+    assert_eq!(eval(&interp, "(next-id)"), "1");
+    assert_eq!(eval(&interp, "(map (fn* (_) (next-id)) [1 2])"), "(2 3)");
+    assert_eq!(calls.get(), 3);
+    assert_eq!(eval(&interp, "(meta (with-meta next-id {:a 1}))"), "{:a 1}");
+}
+
+#[test]
+fn docs() {
+    let interp = Interpreter::new();
+    let vec = interp.get("vec").unwrap();
+    assert_eq!(
+        describe(&vec, "vec").unwrap(),
+        "(vec xs)\n  Returns a vector with the elements of the sequence xs."
+    );
+
+    // a host function named like a builtin doesn't get its docs
+    interp.register("count", count_args);
+    let count = interp.get("count").unwrap();
+    assert_eq!(describe(&count, "count").unwrap(), "count");
+
+    interp.eval_str("(def! recount (fn* () 1))").unwrap();
+    assert_eq!(eval(&interp, "(apropos \"count\")"), "(count recount)");
+    assert_eq!(eval(&Interpreter::new(), "(apropos \"count\")"), "(count)");
+}
+
+#[test]
+fn printer_settings() {
+    // each interpreter has its own *print-length* and *print-level*
+    let a = Interpreter::new();
+    let b = Interpreter::new();
+    a.eval_str("(reset! *print-length* 2)").unwrap();
+    b.eval_str("(reset! *print-level* 1)").unwrap();
+    assert_eq!(eval(&a, "(pr-str [1 2 3 [4]])"), "\"[1 2 ...]\"");
+    assert_eq!(eval(&b, "(pr-str [1 2 3 [4]])"), "\"[1 2 3 #]\"");
+    assert_eq!(
+        eval(&Interpreter::new(), "(pr-str [1 2 3 [4]])"),
+        "\"[1 2 3 [4]]\""
+    );
+}
+
+#[test]
+fn register_fn() {
+    let interp = Interpreter::new();
+    interp.register_fn("repeat", |s: String, n: i64| s.repeat(n as usize));
+    interp.register_fn("answer", || 42);
+    assert_eq!(eval(&interp, "(repeat \"ab\" 3)"), "\"ababab\"");
+    assert_eq!(eval(&interp, "(answer)"), "42");
+    assert_eq!(
+        eval(&interp, "(repeat \"ab\" \"3\")"),
+        "Error: expected int as argument 2 of repeat"
+    );
+    assert_eq!(
+        eval(&interp, "(repeat \"ab\")"),
+        "Error: wrong number of arguments to repeat: expected 2, got 1"
+    );
+}
+
+#[test]
+fn core_arguments() {
+    let interp = Interpreter::new();
+    let cases = [
+        ("(+ 1 :a)", "expected int as argument 2 of +"),
+        (
+            "(nth (list 1))",
+            "wrong number of arguments to nth: expected 2, got 1",
+        ),
+        (
+            "(cons 1)",
+            "wrong number of arguments to cons: expected 2, got 1",
+        ),
+        (
+            "(cons 1 2)",
+            "expected list or vector as argument 2 of cons",
+        ),
+        (
+            "(first)",
+            "wrong number of arguments to first: expected 1, got 0",
+        ),
+        (
+            "(apply)",
+            "wrong number of arguments to apply: expected at least 1, got 0",
+        ),
+        ("(symbol :a)", "expected string as argument 1 of symbol"),
+        ("(/ 1 0)", "/: division by zero"),
+        ("(+ 9223372036854775807 1)", "+: integer overflow"),
+        ("(- -9223372036854775807 2)", "-: integer overflow"),
+        ("(* 4294967296 4294967296)", "*: integer overflow"),
+        ("(/ (- -9223372036854775807 1) -1)", "/: integer overflow"),
+        (
+            "(apply +)",
+            "apply: expected a function and a sequence of arguments",
+        ),
+        (
+            "(apply [1 2])",
+            "apply: expected a function and a sequence of arguments",
+        ),
+    ];
+    for (src, err) in cases.iter() {
+        assert_eq!(eval(&interp, src), format!("Error: {}", err));
+    }
+    assert_eq!(eval(&interp, "(nth [1 2] 1)"), "2");
+    assert_eq!(
+        eval(&interp, "(nth [1 2] -1)"),
+        "Error: nth: index out of range"
+    );
+    assert_eq!(
+        eval(&interp, "(+ 9223372036854775806 1)"),
+        "9223372036854775807"
+    );
+    assert_eq!(eval(&interp, "(* -3 4)"), "-12");
+    assert_eq!(eval(&interp, "+"), "#<fn +>");
+}
+
+#[test]
+fn load_file() {
+    let path = std::env::temp_dir().join(format!("interpreter-{}.mal", std::process::id()));
+    fs::write(&path, "(def! loaded 1)\n(+ loaded 1)\n").unwrap();
+    let interp = Interpreter::new();
+    let res = interp.load_file(&path);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(res.ok(), Some(Int(2)));
+    assert_eq!(interp.get("loaded"), Some(Int(1)));
+    assert_eq!(interp.get("nothing"), None);
+    assert!(interp.load_file(&path).is_err());
+    assert_eq!(interp.eval_str("nil").ok(), Some(Nil));
+}
+
+#[test]
+fn capabilities() {
+    let dir = std::env::temp_dir().join(format!("interpreter-caps-{}", std::process::id()));
+    fs::create_dir_all(dir.join("data")).unwrap();
+    fs::write(dir.join("data").join("in.txt"), "hello").unwrap();
+    let caps = Capabilities {
+        read: Access::Roots(vec![dir.clone()]),
+        write: Access::Roots(vec![dir.join("out")]),
+        ..Capabilities::none()
+    };
+    let interp = Interpreter::with_capabilities(&[], caps);
+    let d = dir.display();
+    let run = |src: String| eval(&interp, &src);
+    assert_eq!(run(format!("(slurp \"{}/data/in.txt\")", d)), "\"hello\"");
+    assert_eq!(
+        run(format!("(slurp \"{}/data/../../in.txt\")", d)),
+        format!(
+            "Error: slurp: permission denied: cannot read {}/data/../../in.txt",
+            d
+        )
+    );
+    assert_eq!(
+        run(format!("(spit \"{}/data/in.txt\" \"bye\")", d)),
+        format!(
+            "Error: spit: permission denied: cannot write {}/data/in.txt",
+            d
+        )
+    );
+    assert_eq!(run(format!("(mkdir \"{}/out/a\")", d)), "nil");
+    assert_eq!(run(format!("(spit \"{}/out/a/x.txt\" 1)", d)), "nil");
+    assert_eq!(
+        run("(load-file \"/etc/passwd\")".to_string()),
+        "Error: load-file: permission denied: cannot read /etc/passwd"
+    );
+    assert_eq!(
+        run("(readline \"> \")".to_string()),
+        "Error: readline: permission denied: cannot read stdin"
+    );
+    assert_eq!(
+        run("(try* (time-ms) (catch* e e))".to_string()),
+        "\"time-ms: permission denied: cannot read the clock\""
+    );
+    assert_eq!(
+        run("(sh \"true\")".to_string()),
+        "Error: sh: permission denied: cannot access the host process"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+
+    // the capabilities of one interpreter don't leak into another
+    assert_eq!(eval(&Interpreter::new(), "(number? (time-ms))"), "true");
+}
+
+#[test]
+fn require() {
+    let dir = std::env::temp_dir().join(format!("interpreter-modules-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("m.mal"), "(def! loads (+ 1 (get-loads)))\n").unwrap();
+    let src = format!("(require \"{}/m\")", dir.display());
+    for _ in 0..2 {
+        // every interpreter loads its modules once
+        let interp = Interpreter::new();
+        let loads = Rc::new(Cell::new(0));
+        let counter = loads.clone();
+        interp.register("get-loads", move |_| {
+            counter.set(counter.get() + 1);
+            Ok(Int(counter.get() - 1))
+        });
+        assert_eq!(eval(&interp, &src), "nil");
+        assert_eq!(eval(&interp, &src), "nil");
+        assert_eq!(interp.get("loads"), Some(Int(1)));
+        assert_eq!(loads.get(), 1);
+
+        // a loaded module isn't read again
+        fs::write(dir.join("m.mal"), b"\xff").unwrap();
+        assert_eq!(eval(&interp, &src), "nil");
+        fs::write(dir.join("m.mal"), "(def! loads (+ 1 (get-loads)))\n").unwrap();
+    }
+
+    // modules outside the sandbox are refused before they are looked for
+    let caps = Capabilities {
+        read: Access::Roots(vec![dir.join("lib")]),
+        ..Capabilities::none()
+    };
+    let interp = Interpreter::with_capabilities(&[], caps);
+    for name in ["m", "missing"].iter() {
+        assert_eq!(
+            eval(
+                &interp,
+                &format!("(require \"{}/{}\")", dir.display(), name)
+            ),
+            format!(
+                "Error: require: permission denied: cannot read {}/{}.mal",
+                dir.display(),
+                name
+            )
+        );
+    }
+
+    // files loaded through the API find their modules next to them
+    fs::write(dir.join("main.mal"), "(require \"util\")\n(+ util 1)\n").unwrap();
+    fs::write(dir.join("util.mal"), "(def! util 41)\n").unwrap();
+    let interp = Interpreter::new();
+    assert_eq!(interp.load_file(dir.join("main.mal")).ok(), Some(Int(42)));
+    fs::remove_dir_all(&dir).unwrap();
+}
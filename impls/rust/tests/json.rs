@@ -0,0 +1,82 @@
+// Convert values to and from JSON through serde
+
+extern crate mal;
+#[macro_use]
+extern crate serde_json;
+
+use mal::types::format_error;
+use mal::{Interpreter, MalVal};
+
+fn eval(interp: &Interpreter, src: &str) -> String {
+    match interp.eval_str(src) {
+        Ok(val) => val.pr_str(true),
+        Err(e) => format!("Error: {}", format_error(e)),
+    }
+}
+
+#[test]
+fn serde() {
+    let interp = Interpreter::new();
+    let val = interp
+        .eval_str("{\"name\" \"mal\" :tags [:lisp 'small] \"steps\" (list 1 2) \"done\" nil}")
+        .unwrap();
+    assert_eq!(
+        serde_json::to_value(&val).unwrap(),
+        json!({"name": "mal", "tags": ["lisp", "small"], "steps": [1, 2], "done": null})
+    );
+
+    let val: MalVal = serde_json::from_value(json!({"a": [1, true, null, "x"]})).unwrap();
+    assert_eq!(val.pr_str(true), "{\"a\" [1 true nil \"x\"]}");
+    let val: MalVal = serde_json::from_str("[2.0, -3]").unwrap();
+    assert_eq!(val.pr_str(true), "[2 -3]");
+    assert!(serde_json::from_str::<MalVal>("1.5").is_err());
+
+    let f = interp.eval_str("[(fn* (x) x)]").unwrap();
+    assert!(serde_json::to_string(&f).is_err());
+}
+
+#[test]
+fn builtins() {
+    let interp = Interpreter::new();
+    let cases = [
+        (
+            "(json-encode {:b [1 \"two\" nil] :a true})",
+            "\"{\\\"a\\\":true,\\\"b\\\":[1,\\\"two\\\",null]}\"",
+        ),
+        (
+            "(json-encode [1 2] {:pretty true})",
+            "\"[\\n  1,\\n  2\\n]\"",
+        ),
+        (
+            "(json-decode \"{\\\"a\\\": [1, {\\\"b\\\": null}]}\")",
+            "{\"a\" [1 {\"b\" nil}]}",
+        ),
+        (
+            "(json-decode \"{\\\"a\\\": [{\\\"b\\\": 1}]}\" {:keywordize true})",
+            "{:a [{:b 1}]}",
+        ),
+        (
+            "(json-encode (atom 1))",
+            "Error: json-encode: cannot encode (atom 1)",
+        ),
+        (
+            "(json-encode [+])",
+            "Error: json-encode: cannot encode #<fn +>",
+        ),
+        (
+            "(json-encode 1 2)",
+            "Error: json-encode: options must be a hash-map",
+        ),
+        (
+            "(json-decode \"[1,\")",
+            "Error: json-decode: EOF while parsing a value at line 1 column 3",
+        ),
+        (
+            "(json-decode \"1e400\")",
+            "Error: json-decode: number out of range at line 1 column 5",
+        ),
+    ];
+    for (src, expected) in cases.iter() {
+        assert_eq!(eval(&interp, src), *expected, "{}", src);
+    }
+}
@@ -0,0 +1,112 @@
+// Run mallint over sample programs
+
+use std::fs;
+use std::process::Command;
+
+// The report for a program and whether it was clean
+fn lint(name: &str, src: &str) -> (String, bool) {
+    let path = std::env::temp_dir().join(format!("mallint-{}-{}.mal", name, std::process::id()));
+    fs::write(&path, src).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_mallint"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    let report = String::from_utf8(out.stdout).unwrap();
+    let prefix = format!("{}:", path.display());
+    (report.replace(&prefix, ""), out.status.success())
+}
+
+#[test]
+fn clean() {
+    let src = ";; comments are fine
+(def! fact (fn* (n) \"Factorial.\" (if (<= n 1) 1 (* n (fact (- n 1))))))
+(def! later (fn* () (earlier)))
+(def! earlier (fn* () (cond (= 1 1) :a :else :b)))
+(def! base 10)
+(defmacro! plus-base (fn* (x) `(+ ~x ~base)))
+(let* [even? (fn* (n) (if (= n 0) true (odd? (- n 1))))
+       odd? (fn* (n) (if (= n 0) false (even? (- n 1))))
+       _ignored 1]
+  (prn (even? 10) (plus-base 1)))
+(try* (throw 1) (catch* e (prn e)))
+";
+    assert_eq!(lint("clean", src), (String::new(), true));
+}
+
+#[test]
+fn warnings() {
+    let src = "(def! f (fn* (a)
+  (let* [b 1
+         c (+ a 1)]
+    (prn c missing))))
+(cons 1)
+(nth [1] 0 1)
+(list)
+(let* [x 1 y] x)
+(let* [1 2] nil)
+(def! count 3)
+(fn* (str) str)
+(defmacro! unless (fn* (c a b) `(if ~c ~b ~a)))
+(unless true
+  undefined-in-macro
+  nil)
+(prn \"unterminated)
+";
+    let (report, ok) = lint("warnings", src);
+    assert!(!ok);
+    assert_eq!(
+        report,
+        "2: unused local b
+4: unbound symbol missing
+5: wrong number of arguments to cons: expected 2, got 1
+6: wrong number of arguments to nth: expected 2, got 3
+8: let* has an odd number of binding forms
+8: unused local y
+9: let* binding name must be a symbol, got 1
+10: count shadows the core function count
+11: str shadows the core function str
+14: unbound symbol undefined-in-macro
+16: expected '\"', got EOF
+"
+    );
+}
+
+#[test]
+fn stray_paren() {
+    assert_eq!(
+        lint("stray", "\n) (prn 1)\n"),
+        ("2: unexpected ')'\n".to_string(), false)
+    );
+}
+
+#[test]
+fn sandboxed() {
+    let file = std::env::temp_dir().join(format!("mallint-pwned-{}", std::process::id()));
+    let src = format!(
+        "(defmacro! m (do (spit {:?} \"x\") (fn* () nil)))
+(defmacro! again (fn* () (list 'again)))
+(again)
+(defmacro! deep (fn* () (deep)))
+(deep)
+",
+        file.display().to_string()
+    );
+    let (report, ok) = lint("sandboxed", &src);
+    assert!(!ok);
+    assert!(
+        !file.exists(),
+        "the linted program wrote {}",
+        file.display()
+    );
+    assert_eq!(
+        report,
+        format!(
+            "1: spit: permission denied: cannot write {}
+3: macro expansion failed: evaluation exceeded the step limit of 5000
+5: macro expansion failed: evaluation exceeded the step limit of 5000
+",
+            file.display()
+        )
+    );
+}
@@ -0,0 +1,254 @@
+// Drive mal_lsp with scripted JSON-RPC over its stdin and stdout
+
+#[macro_use]
+extern crate serde_json;
+
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_mal_lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Client {
+            child,
+            stdin,
+            stdout,
+            next_id: 1,
+        };
+        let init = client.request("initialize", json!({"capabilities": {}}));
+        assert_eq!(init["capabilities"]["hoverProvider"], true);
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, msg: Value) {
+        let body = msg.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(n) = line.strip_prefix("Content-Length:") {
+                length = n.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}));
+    }
+
+    // The whole response, so errors can be checked too
+    fn call(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}));
+        loop {
+            let msg = self.receive();
+            if msg["id"] == id {
+                return msg;
+            }
+        }
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.call(method, params)["result"].clone()
+    }
+
+    // Open a document and return its diagnostics
+    fn open(&mut self, uri: &str, text: &str) -> Vec<Value> {
+        self.notify(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": uri, "languageId": "mal", "version": 1, "text": text}}),
+        );
+        let msg = self.receive();
+        assert_eq!(msg["method"], "textDocument/publishDiagnostics");
+        assert_eq!(msg["params"]["uri"], uri);
+        msg["params"]["diagnostics"].as_array().unwrap().clone()
+    }
+
+    fn at(&mut self, method: &str, uri: &str, line: u64, character: u64) -> Value {
+        self.request(
+            method,
+            json!({
+                "textDocument": {"uri": uri},
+                "position": {"line": line, "character": character},
+            }),
+        )
+    }
+
+    fn stop(mut self) {
+        assert_eq!(self.request("shutdown", Value::Null), Value::Null);
+        self.notify("exit", Value::Null);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+const SOURCE: &str = "(def! sq (fn* (x) \"Square x.\" (* x x)))
+(def! answer 42)
+(defmacro! unless (fn* (c a b) `(if ~c ~b ~a)))
+(sq answer)
+";
+
+#[test]
+fn diagnostics() {
+    let mut client = Client::start();
+    assert!(client.open("file:///ok.mal", SOURCE).is_empty());
+
+    let diags = client.open("file:///open.mal", "(def! f (fn* (x)\n  [x \"a\"]");
+    assert_eq!(diags.len(), 2);
+    assert_eq!(diags[0]["message"], "expected ')', got EOF");
+    assert_eq!(
+        diags[0]["range"]["start"],
+        json!({"line": 0, "character": 0})
+    );
+    assert_eq!(
+        diags[1]["range"]["start"],
+        json!({"line": 0, "character": 8})
+    );
+
+    let diags = client.open("file:///close.mal", "(+ 1 2))\n(- 1 ]");
+    let messages: Vec<&Value> = diags.iter().map(|d| &d["message"]).collect();
+    assert_eq!(
+        messages,
+        vec!["unexpected ')'", "unexpected ']'", "expected ')', got EOF"]
+    );
+    assert_eq!(
+        diags[0]["range"]["start"],
+        json!({"line": 0, "character": 7})
+    );
+
+    let diags = client.open("file:///string.mal", "(str \"abc)");
+    assert_eq!(diags[0]["message"], "expected '\"', got EOF");
+    assert_eq!(
+        diags[0]["range"]["start"],
+        json!({"line": 0, "character": 5})
+    );
+
+    // fixed by a change
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": {"uri": "file:///string.mal", "version": 2},
+            "contentChanges": [{"text": "(str \"abc\")"}],
+        }),
+    );
+    let msg = client.receive();
+    assert_eq!(msg["params"]["diagnostics"], json!([]));
+    client.stop();
+}
+
+#[test]
+fn bad_messages() {
+    let mut client = Client::start();
+    let bad: [&[u8]; 4] = [
+        b"Content-Length: 5\r\n\r\nnope!",
+        b"Content-Length: x\r\n\r\n",
+        b"Content-Length: 99999999999\r\n\r\n",
+        b"Content-Length: 3\r\n\r\n{}}",
+    ];
+    for msg in bad.iter() {
+        client.stdin.write_all(msg).unwrap();
+    }
+    // skipped and logged, the session carries on
+    assert!(client.open("file:///ok.mal", SOURCE).is_empty());
+    client.stop();
+}
+
+#[test]
+fn navigation() {
+    let uri = "file:///defs.mal";
+    let mut client = Client::start();
+    client.open(uri, SOURCE);
+
+    let def = client.at("textDocument/definition", uri, 3, 2);
+    assert_eq!(def["uri"], uri);
+    assert_eq!(
+        def["range"],
+        json!({"start": {"line": 0, "character": 6}, "end": {"line": 0, "character": 8}})
+    );
+    let def = client.at("textDocument/definition", uri, 3, 6);
+    assert_eq!(def["range"]["start"], json!({"line": 1, "character": 6}));
+    assert_eq!(
+        client.at("textDocument/definition", uri, 0, 20),
+        Value::Null
+    );
+
+    let symbols = client.request(
+        "textDocument/documentSymbol",
+        json!({"textDocument": {"uri": uri}}),
+    );
+    let names: Vec<(&Value, &Value)> = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (&s["name"], &s["kind"]))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (&json!("sq"), &json!(12)),
+            (&json!("answer"), &json!(13)),
+            (&json!("unless"), &json!(12))
+        ]
+    );
+    client.stop();
+}
+
+#[test]
+fn hover_and_completion() {
+    let uri = "file:///hover.mal";
+    let mut client = Client::start();
+    client.open(uri, SOURCE);
+
+    let hover = client.at("textDocument/hover", uri, 3, 2);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```mal\n(sq x)\n```\n\nSquare x."
+    );
+    let hover = client.at("textDocument/hover", uri, 0, 31);
+    let value = hover["contents"]["value"].as_str().unwrap();
+    assert!(value.starts_with("```mal\n(* "), "{}", value);
+    assert_eq!(client.at("textDocument/hover", uri, 0, 0), Value::Null);
+
+    let items = client.at("textDocument/completion", uri, 3, 2);
+    let labels: Vec<&str> = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["label"].as_str().unwrap())
+        .collect();
+    assert!(labels.contains(&"sq"), "{:?}", labels);
+    assert!(labels.contains(&"seq"), "{:?}", labels);
+    assert!(labels.iter().all(|l| l.starts_with('s')), "{:?}", labels);
+
+    let resp = client.call("workspace/symbol", json!({"query": ""}));
+    assert_eq!(resp["error"]["code"], -32601);
+    client.stop();
+}
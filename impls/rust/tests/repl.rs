@@ -0,0 +1,43 @@
+// Drive the REPL's colon commands through the library API
+
+extern crate mal;
+
+use mal::eval::{re, repl_env_new};
+use mal::repl::{Command, Repl};
+use mal::types::format_error;
+use mal::MalErr;
+
+fn command(repl: &mut Repl, input: &str) -> String {
+    match repl.command(input) {
+        Some(Ok(out)) => out,
+        Some(Err(e)) => format!("Error: {}", format_error(e)),
+        None => "not a command".to_string(),
+    }
+}
+
+#[test]
+fn doc() {
+    let mut repl = Repl::new(Box::new(|| repl_env_new(&[])), re);
+    assert_eq!(
+        command(&mut repl, ":doc +"),
+        "(+ a b)\n  Returns the sum of a and b."
+    );
+    assert_eq!(command(&mut repl, ":doc if"), "if\n  special form");
+    assert_eq!(command(&mut repl, "(+ 1 2)"), "not a command");
+}
+
+fn cmd_twice(_repl: &mut Repl, args: &str) -> Result<String, MalErr> {
+    Ok(format!("{} {}", args, args))
+}
+
+#[test]
+fn add_command() {
+    let mut repl = Repl::new(Box::new(|| repl_env_new(&[])), re);
+    repl.add_command(Command {
+        name: "twice",
+        usage: ":twice text",
+        help: "print text twice",
+        run: cmd_twice,
+    });
+    assert_eq!(command(&mut repl, ":twice hi"), "hi hi");
+}
@@ -0,0 +1,125 @@
+// Talk to the nREPL-style server over a Unix domain socket
+#![cfg(unix)]
+
+extern crate mal;
+
+use mal::bencode::{self, Bencode};
+use mal::eval::{re, repl_env_new};
+use mal::server;
+use std::collections::BTreeMap;
+use std::io::{BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+struct Client {
+    writer: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl Client {
+    fn connect(path: &Path) -> Client {
+        for _ in 0..200 {
+            if let Ok(stream) = UnixStream::connect(path) {
+                return Client {
+                    writer: stream.try_clone().unwrap(),
+                    reader: BufReader::new(stream),
+                };
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the server did not start");
+    }
+
+    fn send(&mut self, fields: &[(&str, &str)]) -> BTreeMap<String, Bencode> {
+        let req = fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), Bencode::Str(v.to_string())))
+            .collect();
+        bencode::write(&mut self.writer, &Bencode::Dict(req)).unwrap();
+        match bencode::read(&mut self.reader).unwrap() {
+            Some(Bencode::Dict(resp)) => resp,
+            resp => panic!("unexpected response {:?}", resp),
+        }
+    }
+}
+
+fn field<'a>(resp: &'a BTreeMap<String, Bencode>, key: &str) -> Option<&'a str> {
+    match resp.get(key) {
+        Some(Bencode::Str(s)) => Some(s),
+        _ => None,
+    }
+}
+
+fn statuses(resp: &BTreeMap<String, Bencode>) -> Vec<String> {
+    match resp.get("status") {
+        Some(Bencode::List(items)) => items
+            .iter()
+            .map(|s| match s {
+                Bencode::Str(s) => s.clone(),
+                _ => panic!("status {:?} is not a string", s),
+            })
+            .collect(),
+        s => panic!("no status list in {:?}", s),
+    }
+}
+
+#[test]
+fn session() {
+    let path = std::env::temp_dir().join(format!("mal-server-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let addr = format!("unix:{}", path.display());
+    thread::spawn(move || server::serve(&addr, &repl_env_new(&[]), re));
+    let mut client = Client::connect(&path);
+
+    let resp = client.send(&[("op", "describe")]);
+    assert_eq!(statuses(&resp), ["done"]);
+    match resp.get("ops") {
+        Some(Bencode::Dict(ops)) => {
+            for op in ["eval", "load-file", "complete", "interrupt", "describe"].iter() {
+                assert!(ops.contains_key(*op), "missing op {}", op);
+            }
+        }
+        ops => panic!("unexpected ops {:?}", ops),
+    }
+
+    let resp = client.send(&[("op", "eval"), ("code", "(+ 1 2)"), ("id", "7")]);
+    assert_eq!(field(&resp, "value"), Some("3"));
+    assert_eq!(field(&resp, "id"), Some("7"));
+    assert_eq!(statuses(&resp), ["done"]);
+
+    let resp = client.send(&[("op", "eval"), ("code", "(println \"hi\") 4")]);
+    assert_eq!(field(&resp, "out"), Some("hi\n"));
+    assert_eq!(field(&resp, "value"), Some("4"));
+    let resp = client.send(&[("op", "eval"), ("code", "(list *1 *2)")]);
+    assert_eq!(field(&resp, "value"), Some("(4 3)"));
+
+    let resp = client.send(&[("op", "eval"), ("code", "(throw \"boom\")")]);
+    assert_eq!(field(&resp, "err"), Some("\"boom\""));
+    assert_eq!(statuses(&resp), ["done", "error"]);
+    let resp = client.send(&[("op", "eval"), ("code", "*e")]);
+    assert_eq!(field(&resp, "value"), Some("\"boom\""));
+
+    // every form is evaluated, and one that can't be read is an error
+    let resp = client.send(&[("op", "eval"), ("code", "(def! a 1) (def! b 2)")]);
+    assert_eq!(field(&resp, "value"), Some("2"));
+    let resp = client.send(&[("op", "eval"), ("code", "(def! c 3)) (def! d 4)")]);
+    assert_eq!(field(&resp, "err"), Some("unexpected ')'"));
+    let resp = client.send(&[("op", "eval"), ("code", "(list a b c)")]);
+    assert_eq!(field(&resp, "value"), Some("(1 2 3)"));
+    let resp = client.send(&[("op", "eval"), ("code", "")]);
+    assert_eq!(field(&resp, "value"), Some("nil"));
+
+    let resp = client.send(&[("op", "teleport")]);
+    assert_eq!(statuses(&resp), ["done", "error", "unknown-op"]);
+
+    // a peer sending a bogus length is dropped, the others carry on
+    let mut rogue = Client::connect(&path);
+    rogue.writer.write_all(b"99999999999:").unwrap();
+    assert_eq!(bencode::read(&mut rogue.reader).unwrap(), None);
+    let resp = client.send(&[("op", "eval"), ("code", "(* 6 7)")]);
+    assert_eq!(field(&resp, "value"), Some("42"));
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -1,12 +1,21 @@
 use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::hash::{self, Hasher};
+use std::io::{BufReader, BufWriter};
 use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Duration;
 //use std::collections::HashMap;
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet, FnvHasher};
 use itertools::Itertools;
 
 use crate::env::{env_bind, Env};
-use crate::types::MalErr::{ErrMalVal, ErrString};
-use crate::types::MalVal::{Atom, Bool, Func, Hash, Int, List, MalFunc, Nil, Str, Sym, Vector};
+use crate::types::MalErr::{ErrInterrupted, ErrLimit, ErrMalVal, ErrString};
+use crate::types::MalVal::{
+    Atom, Bool, Char, Closure, Func, Handle, Hash, Int, List, MalFunc, Nil, Set, Str, Sym, Tagged,
+    Vector,
+};
 
 #[derive(Debug, Clone)]
 pub enum MalVal {
@@ -15,11 +24,18 @@ pub enum MalVal {
     Int(i64),
     //Float(f64),
     Str(String),
+    // A character from EDN, like \a
+    Char(char),
     Sym(String),
     List(Rc<Vec<MalVal>>, Rc<MalVal>),
     Vector(Rc<Vec<MalVal>>, Rc<MalVal>),
     Hash(Rc<FnvHashMap<String, MalVal>>, Rc<MalVal>),
+    Set(Rc<FnvHashSet<MalVal>>, Rc<MalVal>),
+    // A tagged literal without a reader, like #inst "2020-01-01" in EDN
+    Tagged(String, Rc<MalVal>),
+    Regex(regex::Regex),
     Func(fn(MalArgs) -> MalRet, Rc<MalVal>),
+    Closure(Rc<NativeClosure>, Rc<MalVal>),
     MalFunc {
         eval: fn(ast: MalVal, env: Env) -> MalRet,
         ast: Rc<MalVal>,
@@ -29,12 +45,70 @@ pub enum MalVal {
         meta: Rc<MalVal>,
     },
     Atom(Rc<RefCell<MalVal>>),
+    Handle(Rc<FileHandle>),
+}
+
+// A native function that can capture host state, which a bare Func can't
+pub struct NativeClosure {
+    pub name: String,
+    // The parameters, like "(f & args)", and docstring of a builtin
+    pub doc: Option<(&'static str, &'static str)>,
+    pub f: Box<dyn Fn(MalArgs) -> MalRet>,
+}
+
+impl fmt::Debug for NativeClosure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeClosure({})", self.name)
+    }
+}
+
+// A file opened with open, for reading or for writing. Closing it drops
+// the file, so it is None afterwards.
+pub struct FileHandle {
+    pub path: String,
+    pub file: RefCell<Option<FileIo>>,
+}
+
+pub enum FileIo {
+    Read(BufReader<File>),
+    Write(BufWriter<File>),
+}
+
+impl fmt::Debug for FileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FileHandle({})", self.path)
+    }
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum MalErr {
     ErrString(String),
     ErrMalVal(MalVal),
+    ErrLimit(Limit),
+    ErrInterrupted,
+}
+
+// The evaluation budget that was exhausted, together with its configured value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Steps(u64),
+    Timeout(Duration),
+    Alloc(u64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Steps(n) => write!(f, "evaluation exceeded the step limit of {}", n),
+            Limit::Timeout(d) => write!(
+                f,
+                "evaluation exceeded the time limit of {}ms",
+                d.as_millis()
+            ),
+            Limit::Alloc(n) => write!(f, "evaluation exceeded the allocation limit of {}", n),
+        }
+    }
 }
 
 pub type MalArgs = Vec<MalVal>;
@@ -42,6 +116,7 @@ pub type MalRet = Result<MalVal, MalErr>;
 
 // type utility macros
 
+#[macro_export]
 macro_rules! list {
   ($seq:expr) => {{
     List(Rc::new($seq),Rc::new(Nil))
@@ -52,6 +127,7 @@ macro_rules! list {
   }}
 }
 
+#[macro_export]
 macro_rules! vector {
   ($seq:expr) => {{
     Vector(Rc::new($seq),Rc::new(Nil))
@@ -72,6 +148,8 @@ pub fn format_error(e: MalErr) -> String {
     match e {
         ErrString(s) => s.clone(),
         ErrMalVal(mv) => mv.pr_str(true),
+        ErrLimit(l) => l.to_string(),
+        ErrInterrupted => "interrupted".to_string(),
     }
 }
 
@@ -91,6 +169,7 @@ impl MalVal {
     pub fn empty_q(&self) -> MalRet {
         match self {
             List(l, _) | Vector(l, _) => Ok(Bool(l.len() == 0)),
+            Set(s, _) => Ok(Bool(s.is_empty())),
             Nil => Ok(Bool(true)),
             _ => error("invalid type for empty?"),
         }
@@ -99,6 +178,8 @@ impl MalVal {
     pub fn count(&self) -> MalRet {
         match self {
             List(l, _) | Vector(l, _) => Ok(Int(l.len() as i64)),
+            Set(s, _) => Ok(Int(s.len() as i64)),
+            Str(s) if !self.keyword_q() => Ok(Int(s.chars().count() as i64)),
             Nil => Ok(Int(0)),
             _ => error("invalid type for count"),
         }
@@ -107,6 +188,7 @@ impl MalVal {
     pub fn apply(&self, args: MalArgs) -> MalRet {
         match *self {
             Func(f, _) => f(args),
+            Closure(ref c, _) => (c.f)(args),
             MalFunc {
                 eval,
                 ref ast,
@@ -162,8 +244,8 @@ impl MalVal {
 
     pub fn get_meta(&self) -> MalRet {
         match self {
-            List(_, meta) | Vector(_, meta) | Hash(_, meta) => Ok((&**meta).clone()),
-            Func(_, meta) => Ok((&**meta).clone()),
+            List(_, meta) | Vector(_, meta) | Hash(_, meta) | Set(_, meta) => Ok((&**meta).clone()),
+            Func(_, meta) | Closure(_, meta) => Ok((&**meta).clone()),
             MalFunc { meta, .. } => Ok((&**meta).clone()),
             _ => error("meta not supported by type"),
         }
@@ -174,7 +256,9 @@ impl MalVal {
             List(_, ref mut meta)
             | Vector(_, ref mut meta)
             | Hash(_, ref mut meta)
+            | Set(_, ref mut meta)
             | Func(_, ref mut meta)
+            | Closure(_, ref mut meta)
             | MalFunc { ref mut meta, .. } => {
                 *meta = Rc::new((&*new_meta).clone());
             }
@@ -191,22 +275,313 @@ impl PartialEq for MalVal {
             (Bool(ref a), Bool(ref b)) => a == b,
             (Int(ref a), Int(ref b)) => a == b,
             (Str(ref a), Str(ref b)) => a == b,
+            (Char(a), Char(b)) => a == b,
             (Sym(ref a), Sym(ref b)) => a == b,
             (List(ref a, _), List(ref b, _))
             | (Vector(ref a, _), Vector(ref b, _))
             | (List(ref a, _), Vector(ref b, _))
             | (Vector(ref a, _), List(ref b, _)) => a == b,
             (Hash(ref a, _), Hash(ref b, _)) => a == b,
-            (MalFunc { .. }, MalFunc { .. }) => false,
+            (Set(ref a, _), Set(ref b, _)) => a == b,
+            (Tagged(ref t, ref a), Tagged(ref u, ref b)) => t == u && a == b,
+            (MalVal::Regex(ref a), MalVal::Regex(ref b)) => a.as_str() == b.as_str(),
+            // Functions and atoms are only equal to themselves
+            (Func(a, _), Func(b, _)) => std::ptr::fn_addr_eq(*a, *b),
+            (Closure(ref a, _), Closure(ref b, _)) => Rc::ptr_eq(a, b),
+            (
+                MalFunc {
+                    ast: ref a,
+                    env: ref e,
+                    params: ref p,
+                    is_macro: m,
+                    ..
+                },
+                MalFunc {
+                    ast: ref b,
+                    env: ref f,
+                    params: ref q,
+                    is_macro: n,
+                    ..
+                },
+            ) => Rc::ptr_eq(a, b) && Rc::ptr_eq(e, f) && Rc::ptr_eq(p, q) && m == n,
+            (Atom(ref a), Atom(ref b)) => Rc::ptr_eq(a, b),
+            (Handle(ref a), Handle(ref b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
 }
 
+impl Eq for MalVal {}
+
+// The combined hash of items that is the same whatever their order
+fn hash_unordered<T: hash::Hash, I: Iterator<Item = T>, H: Hasher>(items: I, state: &mut H) {
+    let sum = items.fold(0u64, |sum, item| {
+        let mut h = FnvHasher::default();
+        item.hash(&mut h);
+        sum.wrapping_add(h.finish())
+    });
+    state.write_u64(sum);
+}
+
+// Consistent with equality, so lists and vectors with the same elements
+// hash the same, and functions and atoms hash their identity
+impl hash::Hash for MalVal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Nil => state.write_u8(0),
+            Bool(b) => b.hash(state),
+            Int(i) => i.hash(state),
+            Str(s) | Sym(s) => s.hash(state),
+            Char(c) => c.hash(state),
+            List(v, _) | Vector(v, _) => v.hash(state),
+            Hash(hm, _) => hash_unordered(hm.iter(), state),
+            Set(s, _) => hash_unordered(s.iter(), state),
+            Tagged(t, v) => {
+                t.hash(state);
+                v.hash(state);
+            }
+            MalVal::Regex(r) => r.as_str().hash(state),
+            Func(f, _) => (*f as usize).hash(state),
+            Closure(c, _) => Rc::as_ptr(c).hash(state),
+            MalFunc { ast, .. } => Rc::as_ptr(ast).hash(state),
+            Atom(a) => Rc::as_ptr(a).hash(state),
+            Handle(h) => Rc::as_ptr(h).hash(state),
+        }
+    }
+}
+
 pub fn func(f: fn(MalArgs) -> MalRet) -> MalVal {
     Func(f, Rc::new(Nil))
 }
 
+pub fn closure<F: Fn(MalArgs) -> MalRet + 'static>(name: &str, f: F) -> MalVal {
+    let c = NativeClosure {
+        name: name.to_string(),
+        doc: None,
+        f: Box::new(f),
+    };
+    Closure(Rc::new(c), Rc::new(Nil))
+}
+
+// A closure that doc describes with params and docstring
+pub fn builtin<F: Fn(MalArgs) -> MalRet + 'static>(
+    name: &str,
+    params: &'static str,
+    docstring: &'static str,
+    f: F,
+) -> MalVal {
+    let c = NativeClosure {
+        name: name.to_string(),
+        doc: Some((params, docstring)),
+        f: Box::new(f),
+    };
+    Closure(Rc::new(c), Rc::new(Nil))
+}
+
+// Conversions between mal values and the arguments and results of typed
+// native functions
+
+pub trait FromMal: Sized {
+    // The name of the type in error messages
+    const TYPE: &'static str;
+    fn from_mal(mv: &MalVal) -> Option<Self>;
+}
+
+pub trait IntoMal {
+    fn into_mal(self) -> MalRet;
+}
+
+impl FromMal for MalVal {
+    const TYPE: &'static str = "value";
+    fn from_mal(mv: &MalVal) -> Option<MalVal> {
+        Some(mv.clone())
+    }
+}
+
+impl FromMal for i64 {
+    const TYPE: &'static str = "int";
+    fn from_mal(mv: &MalVal) -> Option<i64> {
+        match mv {
+            Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+impl FromMal for bool {
+    const TYPE: &'static str = "bool";
+    fn from_mal(mv: &MalVal) -> Option<bool> {
+        match mv {
+            Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromMal for String {
+    const TYPE: &'static str = "string";
+    fn from_mal(mv: &MalVal) -> Option<String> {
+        match mv {
+            Str(s) if !mv.keyword_q() => Some(s.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl FromMal for regex::Regex {
+    const TYPE: &'static str = "regex";
+    fn from_mal(mv: &MalVal) -> Option<regex::Regex> {
+        match mv {
+            MalVal::Regex(r) => Some(r.clone()),
+            _ => None,
+        }
+    }
+}
+
+// The elements of a list or vector, shared rather than copied
+impl FromMal for Rc<Vec<MalVal>> {
+    const TYPE: &'static str = "list or vector";
+    fn from_mal(mv: &MalVal) -> Option<Rc<Vec<MalVal>>> {
+        match mv {
+            List(v, _) | Vector(v, _) => Some(v.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromMal for Rc<FileHandle> {
+    const TYPE: &'static str = "file";
+    fn from_mal(mv: &MalVal) -> Option<Rc<FileHandle>> {
+        match mv {
+            Handle(h) => Some(h.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl IntoMal for MalVal {
+    fn into_mal(self) -> MalRet {
+        Ok(self)
+    }
+}
+
+impl IntoMal for i64 {
+    fn into_mal(self) -> MalRet {
+        Ok(Int(self))
+    }
+}
+
+impl IntoMal for bool {
+    fn into_mal(self) -> MalRet {
+        Ok(Bool(self))
+    }
+}
+
+impl IntoMal for String {
+    fn into_mal(self) -> MalRet {
+        Ok(Str(self))
+    }
+}
+
+impl IntoMal for () {
+    fn into_mal(self) -> MalRet {
+        Ok(Nil)
+    }
+}
+
+impl IntoMal for MalArgs {
+    fn into_mal(self) -> MalRet {
+        Ok(list!(self))
+    }
+}
+
+impl<T: IntoMal> IntoMal for Option<T> {
+    fn into_mal(self) -> MalRet {
+        match self {
+            Some(v) => v.into_mal(),
+            None => Ok(Nil),
+        }
+    }
+}
+
+impl<T: IntoMal> IntoMal for Result<T, MalErr> {
+    fn into_mal(self) -> MalRet {
+        self?.into_mal()
+    }
+}
+
+pub fn check_arity(
+    name: &str,
+    args: &[MalVal],
+    required: usize,
+    variadic: bool,
+) -> Result<(), MalErr> {
+    if args.len() < required || (!variadic && args.len() > required) {
+        let expected = match variadic {
+            true => format!("at least {}", required),
+            false => required.to_string(),
+        };
+        return Err(ErrString(format!(
+            "wrong number of arguments to {}: expected {}, got {}",
+            name,
+            expected,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+pub fn arg<T: FromMal>(name: &str, args: &[MalVal], i: usize) -> Result<T, MalErr> {
+    T::from_mal(&args[i]).ok_or_else(|| {
+        ErrString(format!(
+            "expected {} as argument {} of {}",
+            T::TYPE,
+            i + 1,
+            name
+        ))
+    })
+}
+
+// A Rust function taking a fixed number of FromMal arguments, where Args is
+// the tuple of their types
+pub trait NativeFn<Args> {
+    const ARITY: usize;
+    fn call(&self, name: &str, args: &[MalVal]) -> MalRet;
+}
+
+macro_rules! native_fn {
+    ($arity:expr; $($t:ident $i:expr),*) => {
+        impl<F, R, $($t),*> NativeFn<($($t,)*)> for F
+        where
+            F: Fn($($t),*) -> R,
+            R: IntoMal,
+            $($t: FromMal,)*
+        {
+            const ARITY: usize = $arity;
+            #[allow(unused_variables)]
+            fn call(&self, name: &str, args: &[MalVal]) -> MalRet {
+                self($(arg::<$t>(name, args, $i)?),*).into_mal()
+            }
+        }
+    };
+}
+
+native_fn!(0;);
+native_fn!(1; A 0);
+native_fn!(2; A 0, B 1);
+native_fn!(3; A 0, B 1, C 2);
+native_fn!(4; A 0, B 1, C 2, D 3);
+
+// Wrap a typed Rust function as a native named name, which checks the number
+// and the types of its arguments
+pub fn typed_fn<Args, F: NativeFn<Args> + 'static>(name: &str, f: F) -> MalVal {
+    let owned = name.to_string();
+    closure(name, move |args| {
+        check_arity(&owned, &args, F::ARITY, false)?;
+        f.call(&owned, &args)
+    })
+}
+
 pub fn _assoc(mut hm: FnvHashMap<String, MalVal>, kvs: MalArgs) -> MalRet {
     if kvs.len() % 2 != 0 {
         return error("odd number of elements");
@@ -234,6 +609,35 @@ pub fn _dissoc(mut hm: FnvHashMap<String, MalVal>, ks: MalArgs) -> MalRet {
     Ok(Hash(Rc::new(hm), Rc::new(Nil)))
 }
 
+pub fn set(items: MalArgs) -> MalVal {
+    Set(Rc::new(items.into_iter().collect()), Rc::new(Nil))
+}
+
+// The compiled regex for pattern. Compiling is much slower than matching,
+// so patterns are cached, and the cache is emptied when it grows too large
+// for patterns built at runtime to use up memory.
+pub fn compile_regex(pattern: &str) -> Result<regex::Regex, MalErr> {
+    lazy_static! {
+        static ref PATTERNS: Mutex<FnvHashMap<String, regex::Regex>> =
+            Mutex::new(FnvHashMap::default());
+    }
+    let mut patterns = PATTERNS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(re) = patterns.get(pattern) {
+        return Ok(re.clone());
+    }
+    let re = regex::Regex::new(pattern)
+        .map_err(|e| ErrString(format!("invalid regex #\"{}\": {}", pattern, e)))?;
+    if patterns.len() >= 256 {
+        patterns.clear();
+    }
+    patterns.insert(pattern.to_string(), re.clone());
+    Ok(re)
+}
+
+pub fn re_pattern(pattern: &str) -> MalRet {
+    compile_regex(pattern).map(MalVal::Regex)
+}
+
 pub fn hash_map(kvs: MalArgs) -> MalRet {
     let hm: FnvHashMap<String, MalVal> = FnvHashMap::default();
     _assoc(hm, kvs)
@@ -0,0 +1,79 @@
+use mal::format::format;
+use std::io::{self, Read, Write};
+use std::process::exit;
+use std::{env, fs};
+
+const USAGE: &str = "usage: malfmt [--check] [FILE...]
+
+Reformats the given mal files in place, or standard input to standard
+output when there are none. With --check nothing is written, the files
+that are not formatted are listed and the exit status is 1.";
+
+fn main() {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                eprintln!("malfmt: unknown option {}\n\n{}", arg, USAGE);
+                exit(2);
+            }
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        files.push(String::from("-"));
+    }
+
+    let mut failed = false;
+    for file in &files {
+        let input = if file == "-" {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).map(|_| input)
+        } else {
+            fs::read_to_string(file)
+        };
+        let input = match input {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("malfmt: {}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+        let output = match format(&input) {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("malfmt: {}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let written = if check {
+            if output != input {
+                println!("{}", file);
+                failed = true;
+            }
+            Ok(())
+        } else if file == "-" {
+            io::stdout().write_all(output.as_bytes())
+        } else if output != input {
+            fs::write(file, output)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = written {
+            eprintln!("malfmt: {}: {}", file, e);
+            failed = true;
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
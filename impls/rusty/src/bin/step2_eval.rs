@@ -1,18 +1,36 @@
-use mal::env::default_environment;
+use std::env;
+
+use mal::env::{default_environment, set_command_line_args};
 use mal::mal::*;
+use mal::repl::{incomplete, MalHelper};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
 fn main() {
-    let mut rl = Editor::<()>::new().expect("Failed to initialize readline");
+    let mut rl = Editor::<MalHelper>::new().expect("Failed to initialize readline");
     let env = default_environment();
+    set_command_line_args(&env, &env::args().skip(1).collect::<Vec<_>>());
+    rl.set_helper(Some(MalHelper::new(env.clone())));
+    let mut input = String::new();
     loop {
-        let readline = rl.readline("user> ");
-        match readline {
+        let prompt = if input.is_empty() { "user> " } else { "  ... " };
+        match rl.readline(prompt) {
             Ok(line) => {
-                rep(&env, &line);
-                rl.add_history_entry(line.as_str());
+                if !input.is_empty() {
+                    input.push('\n');
+                }
+                input.push_str(&line);
+                if incomplete(&input) {
+                    continue;
+                }
+                rep(&env, &input);
+                rl.add_history_entry(input.as_str());
+                if let Some(helper) = rl.helper() {
+                    helper.observe(&input);
+                }
+                input.clear();
             }
+            Err(ReadlineError::Interrupted) if !input.is_empty() => input.clear(),
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
                 break;
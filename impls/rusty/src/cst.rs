@@ -0,0 +1,165 @@
+use crate::errors::*;
+use crate::reader::{scan, Tokens};
+use std::fmt;
+
+/// A node of the concrete syntax tree. Unlike the values produced by the
+/// reader it keeps everything found in the source, whitespace and comments
+/// included, so printing the nodes gives back the exact input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// Whitespace and commas between two tokens
+    Space(String),
+    Comment(String),
+    /// One of the reader macros `'`, `` ` ``, `~`, `~@`, `@` and `^`, which
+    /// apply to the form that follows
+    Prefix(String),
+    Atom(String),
+    String(String),
+    List(Vec<Node>),
+    Array(Vec<Node>),
+    Map(Vec<Node>),
+    Set(Vec<Node>),
+}
+
+const PREFIXES: [&str; 6] = ["'", "`", "~", "~@", "@", "^"];
+
+/// Parses `input` into its top level nodes. Fails on the inputs the reader
+/// rejects because of their delimiters, i.e. unbalanced brackets and
+/// unterminated strings.
+pub fn parse(input: &str) -> TokenizerResult<Vec<Node>> {
+    // The open sequences, innermost last, each with its children so far
+    let mut stack: Vec<(Tokens, Vec<Node>)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut end = 0;
+
+    for (range, token) in scan(input) {
+        let children = match stack.last_mut() {
+            Some((_, children)) => children,
+            None => &mut nodes,
+        };
+        if range.start > end {
+            children.push(Node::Space(input[end..range.start].to_string()));
+        }
+        end = range.end;
+
+        match token {
+            Tokens::LeftParen
+            | Tokens::LeftSquareBraket
+            | Tokens::LeftBraket
+            | Tokens::HashBraket => stack.push((token, Vec::new())),
+            Tokens::RightParen | Tokens::RightSquareBraket | Tokens::RightBraket => {
+                let node = match (stack.pop(), token) {
+                    (Some((Tokens::LeftParen, c)), Tokens::RightParen) => Node::List(c),
+                    (Some((Tokens::LeftSquareBraket, c)), Tokens::RightSquareBraket) => {
+                        Node::Array(c)
+                    }
+                    (Some((Tokens::LeftBraket, c)), Tokens::RightBraket) => Node::Map(c),
+                    (Some((Tokens::HashBraket, c)), Tokens::RightBraket) => Node::Set(c),
+                    (_, Tokens::RightParen) => {
+                        return Err(TokenizerError::Paren(String::from("unexpected ')'")))
+                    }
+                    (_, Tokens::RightSquareBraket) => {
+                        return Err(TokenizerError::Braket(String::from("unexpected ']'")))
+                    }
+                    _ => return Err(TokenizerError::Braket(String::from("unexpected '}'"))),
+                };
+                match stack.last_mut() {
+                    Some((_, children)) => children.push(node),
+                    None => nodes.push(node),
+                }
+            }
+            Tokens::TildeAt => children.push(Node::Prefix(String::from("~@"))),
+            Tokens::Comment(text) => children.push(Node::Comment(text)),
+            Tokens::String(text) | Tokens::Regex(text) => {
+                let quoted = text.strip_prefix('#').unwrap_or(&text);
+                if quoted.len() < 2 || !quoted.ends_with('\"') {
+                    return Err(TokenizerError::Quote(format!(
+                        "unterminated quote starting at {}",
+                        text
+                    )));
+                }
+                children.push(Node::String(text))
+            }
+            Tokens::Atom(text) if PREFIXES.contains(&text.as_str()) => {
+                children.push(Node::Prefix(text))
+            }
+            Tokens::Atom(text) => children.push(Node::Atom(text)),
+        }
+    }
+
+    match stack.pop() {
+        None => {
+            if end < input.len() {
+                nodes.push(Node::Space(input[end..].to_string()));
+            }
+            Ok(nodes)
+        }
+        Some((Tokens::LeftParen, _)) => Err(TokenizerError::UnbalancedList),
+        Some((Tokens::LeftSquareBraket, _)) => Err(TokenizerError::UnbalancedArray),
+        Some((Tokens::HashBraket, _)) => Err(TokenizerError::UnbalancedSet),
+        Some(_) => Err(TokenizerError::UnbalancedMap),
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (open, children, close) = match self {
+            Node::Space(text)
+            | Node::Comment(text)
+            | Node::Prefix(text)
+            | Node::Atom(text)
+            | Node::String(text) => return write!(f, "{}", text),
+            Node::List(children) => ("(", children, ")"),
+            Node::Array(children) => ("[", children, "]"),
+            Node::Map(children) => ("{", children, "}"),
+            Node::Set(children) => ("#{", children, "}"),
+        };
+        write!(f, "{}", open)?;
+        for child in children {
+            write!(f, "{}", child)?;
+        }
+        write!(f, "{}", close)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn source(nodes: &[Node]) -> String {
+        nodes.iter().map(|node| node.to_string()).collect()
+    }
+
+    #[test]
+    fn testing_lossless_parse() {
+        let input =
+            "; header\n\n(def! f (fn* [a, b] ; args\n  {:a 'a :b `(~@b)}))\n\n@x ^{:m 1} y  \n";
+        let nodes = parse(input).expect("We should be able to parse the input");
+        assert_eq!(source(&nodes), input);
+        assert_eq!(nodes[0], Node::Comment(String::from("; header")));
+        assert_eq!(nodes[1], Node::Space(String::from("\n\n")));
+        assert_eq!(
+            parse("'(a \"b\")").unwrap(),
+            vec![
+                Node::Prefix(String::from("'")),
+                Node::List(vec![
+                    Node::Atom(String::from("a")),
+                    Node::Space(String::from(" ")),
+                    Node::String(String::from("\"b\"")),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn testing_parse_errors() {
+        assert_eq!(
+            parse("(a [b)"),
+            Err(TokenizerError::Paren(String::from("unexpected ')'")))
+        );
+        assert_eq!(parse("(a [b]"), Err(TokenizerError::UnbalancedList));
+        assert_eq!(parse("{:a"), Err(TokenizerError::UnbalancedMap));
+        assert!(parse("(str \"abc)").unwrap_err().is_incomplete());
+        assert_eq!(parse("").unwrap(), vec![]);
+    }
+}
@@ -3,7 +3,8 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use crate::{
     errors::{eval_err, RuntimeError, RuntimeResult},
     list::List,
-    types::{arithmetic_function, comp_function, Symbol, Value},
+    mal::call_function,
+    types::{arithmetic_function, comp_function, Regex, Set, Symbol, Value},
 };
 
 pub type RcEnv = Rc<RefCell<Env>>;
@@ -63,6 +64,16 @@ impl Env {
             None
         }
     }
+
+    /// Every symbol visible from this environment, including the ones bound
+    /// in its parents. Shadowed symbols are listed once per binding.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.entries.keys().cloned().collect();
+        if let Some(parent) = &self.parent {
+            symbols.extend(parent.borrow().symbols());
+        }
+        symbols
+    }
 }
 
 pub fn new_env(env: RcEnv) -> RcEnv {
@@ -139,7 +150,10 @@ pub fn default_environment() -> RcEnv {
             let first = args
                 .first()
                 .ok_or_else(|| eval_err("empty? requires a list as argument, none given"))?;
-            first.expect_list().map(|list| list.empty().into())
+            match first {
+                Value::Set(set) => Ok(set.is_empty().into()),
+                _ => first.expect_list().map(|list| list.is_empty().into()),
+            }
         }),
     );
 
@@ -151,6 +165,8 @@ pub fn default_environment() -> RcEnv {
                 .ok_or_else(|| eval_err("count requires a list as argument, none given"))?;
             if matches!(first, Value::Nil) {
                 Ok(Value::Integer(0))
+            } else if let Value::Set(set) = first {
+                Ok((set.len() as i64).into())
             } else {
                 first
                     .expect_list()
@@ -170,7 +186,274 @@ pub fn default_environment() -> RcEnv {
             Ok(Value::List(list.reverse()))
         }),
     );
-    Rc::new(RefCell::new(env))
+
+    env.add(
+        Symbol::from("set"),
+        Value::NativeFun(|_, args| {
+            let first = args
+                .first()
+                .ok_or_else(|| eval_err("set requires a collection as argument, none given"))?;
+            match first {
+                Value::Nil => Ok(Value::Set(Set::default())),
+                Value::Set(_) => Ok(first.clone()),
+                Value::List(list) | Value::Array(list) => {
+                    Ok(Value::Set(list.iter().cloned().collect()))
+                }
+                val => Err(eval_err(&format!("Value '{val}' is not a collection"))),
+            }
+        }),
+    );
+
+    env.add(
+        Symbol::from("set?"),
+        Value::NativeFun(|_, args| {
+            let first = args
+                .first()
+                .ok_or_else(|| eval_err("set? requires a value as argument, none given"))?;
+            Ok(matches!(first, Value::Set(_)).into())
+        }),
+    );
+
+    env.add(
+        Symbol::from("conj"),
+        Value::NativeFun(|_, args| {
+            let (first, items) = args
+                .split_first()
+                .ok_or_else(|| eval_err("conj requires a collection as argument, none given"))?;
+            let items = items.iter().cloned();
+            match first {
+                Value::Nil => Ok(Value::List(
+                    items.fold(List::new(), |acc, e| acc.prepend(e)),
+                )),
+                Value::List(list) => Ok(Value::List(
+                    items.fold(list.clone(), |acc, e| acc.prepend(e)),
+                )),
+                Value::Array(array) => {
+                    Ok(Value::Array(array.iter().cloned().chain(items).collect()))
+                }
+                Value::Set(set) => Ok(Value::Set(items.fold(set.clone(), |acc, e| acc.insert(e)))),
+                val => Err(eval_err(&format!("Value '{val}' is not a collection"))),
+            }
+        }),
+    );
+
+    env.add(
+        Symbol::from("disj"),
+        Value::NativeFun(|_, args| {
+            let (first, items) = args
+                .split_first()
+                .ok_or_else(|| eval_err("disj requires a set as argument, none given"))?;
+            let set = first.expect_set()?;
+            Ok(Value::Set(
+                items.iter().fold(set.clone(), |acc, e| acc.remove(e)),
+            ))
+        }),
+    );
+
+    env.add(
+        Symbol::from("contains?"),
+        Value::NativeFun(|_, args| match args.as_slice() {
+            [Value::Set(set), key] => Ok(set.contains(key).into()),
+            [Value::Map(map), key] => Ok(map.iter().step_by(2).any(|k| k == key).into()),
+            [Value::Nil, _] => Ok(Value::False),
+            [coll, _] => Err(eval_err(&format!("Value '{coll}' is not a set or a map"))),
+            _ => Err(eval_err(
+                "contains? requires a collection and a key as arguments",
+            )),
+        }),
+    );
+
+    env.add(
+        Symbol::from("union"),
+        Value::NativeFun(|_, args| set_operation("union", args, Set::union)),
+    );
+
+    env.add(
+        Symbol::from("intersection"),
+        Value::NativeFun(|_, args| set_operation("intersection", args, Set::intersection)),
+    );
+
+    env.add(
+        Symbol::from("difference"),
+        Value::NativeFun(|_, args| set_operation("difference", args, Set::difference)),
+    );
+
+    env.add(
+        Symbol::from("re-pattern"),
+        Value::NativeFun(|_, args| match args.as_slice() {
+            [Value::Regex(regex)] => Ok(Value::Regex(regex.clone())),
+            [pattern] => {
+                let pattern = pattern.expect_string()?;
+                Regex::new(&pattern)
+                    .map(Value::Regex)
+                    .map_err(|e| eval_err(&format!("invalid regex #\"{pattern}\": {e}")))
+            }
+            _ => Err(eval_err("re-pattern requires a string as argument")),
+        }),
+    );
+
+    env.add(
+        Symbol::from("re-find"),
+        Value::NativeFun(|_, args| {
+            let (regex, text) = regex_args("re-find", &args)?;
+            Ok(regex
+                .as_regex()
+                .captures(&text)
+                .map_or(Value::Nil, |caps| groups(&caps)))
+        }),
+    );
+
+    env.add(
+        Symbol::from("re-matches"),
+        Value::NativeFun(|_, args| {
+            let (regex, text) = regex_args("re-matches", &args)?;
+            let whole = Regex::new(&format!(r"\A(?:{})\z", regex.as_str()))
+                .map_err(|e| eval_err(&e.to_string()))?;
+            Ok(whole
+                .as_regex()
+                .captures(&text)
+                .map_or(Value::Nil, |caps| groups(&caps)))
+        }),
+    );
+
+    env.add(
+        Symbol::from("re-seq"),
+        Value::NativeFun(|_, args| {
+            let (regex, text) = regex_args("re-seq", &args)?;
+            let matches: List<Value> = regex
+                .as_regex()
+                .captures_iter(&text)
+                .map(|caps| groups(&caps))
+                .collect();
+            if matches.is_empty() {
+                Ok(Value::Nil)
+            } else {
+                Ok(Value::List(matches))
+            }
+        }),
+    );
+
+    env.add(
+        Symbol::from("replace"),
+        Value::NativeFun(|env, args| replace(&env, "replace", &args, 1)),
+    );
+
+    env.add(
+        Symbol::from("replace-all"),
+        Value::NativeFun(|env, args| replace(&env, "replace-all", &args, 0)),
+    );
+    let env = Rc::new(RefCell::new(env));
+    set_command_line_args(&env, &[]);
+    env
+}
+
+/// Binds `*ARGV*` and its alias `*command-line-args*` to the arguments the
+/// interpreter was started with
+pub fn set_command_line_args(env: &RcEnv, args: &[String]) {
+    let args: List<Value> = args.iter().map(|arg| Value::string(arg)).collect();
+    let mut env = env.borrow_mut();
+    env.add(Symbol::from("*ARGV*"), Value::List(args.clone()));
+    env.add(Symbol::from("*command-line-args*"), Value::List(args));
+}
+
+/// The regex and the text that the regex function `name` is called with
+fn regex_args(name: &str, args: &[Value]) -> RuntimeResult<(Regex, String)> {
+    match args {
+        [regex, text] => Ok((regex.expect_regex()?.clone(), text.expect_string()?)),
+        _ => Err(eval_err(&format!(
+            "{name} requires a regex and a string as arguments"
+        ))),
+    }
+}
+
+/// A match of a regex: the matched text when the regex has no groups,
+/// otherwise an array of it followed by the groups, nil for the groups that
+/// didn't take part in the match
+fn groups(caps: &regex::Captures) -> Value {
+    let text = |m: Option<regex::Match>| m.map_or(Value::Nil, |m| Value::string(m.as_str()));
+    if caps.len() == 1 {
+        text(caps.get(0))
+    } else {
+        Value::Array(caps.iter().map(text).collect())
+    }
+}
+
+/// Replaces the first `limit` matches in a string, all of them when `limit`
+/// is 0. The match is a string or a regex and the replacement either a
+/// string, where `$1` refers to a group of a regex, or a function called
+/// with each match that returns its replacement
+fn replace(env: &RcEnv, name: &str, args: &[Value], limit: usize) -> RuntimeResult<Value> {
+    let [text, pattern, replacement] = args else {
+        return Err(eval_err(&format!(
+            "{name} requires a string, a match and a replacement as arguments"
+        )));
+    };
+    let text = text.expect_string()?;
+    let (regex, literal) = match pattern {
+        Value::Regex(regex) => (regex.clone(), false),
+        Value::String(_) => {
+            let escaped = regex::escape(&pattern.expect_string()?);
+            let regex = Regex::new(&escaped).map_err(|e| eval_err(&e.to_string()))?;
+            (regex, true)
+        }
+        val => {
+            return Err(eval_err(&format!(
+                "{name} requires a string or a regex to match, got '{val}'"
+            )))
+        }
+    };
+    let regex = regex.as_regex();
+    let replaced = match replacement {
+        Value::String(_) if literal => {
+            let replacement = replacement.expect_string()?;
+            regex
+                .replacen(&text, limit, regex::NoExpand(&replacement))
+                .into_owned()
+        }
+        Value::String(_) => {
+            let replacement = replacement.expect_string()?;
+            regex
+                .replacen(&text, limit, replacement.as_str())
+                .into_owned()
+        }
+        Value::NativeFun(_) | Value::NativeClosure(_) | Value::LispClosure(_) => {
+            let limit = if limit == 0 { usize::MAX } else { limit };
+            let mut replaced = String::new();
+            let mut last = 0;
+            for caps in regex.captures_iter(&text).take(limit) {
+                let (start, end) = caps.get(0).map_or((0, 0), |m| (m.start(), m.end()));
+                replaced.push_str(&text[last..start]);
+                match call_function(env, replacement.clone(), vec![groups(&caps)])? {
+                    value @ Value::String(_) => replaced.push_str(&value.expect_string()?),
+                    value => replaced.push_str(&value.to_string()),
+                }
+                last = end;
+            }
+            replaced.push_str(&text[last..]);
+            replaced
+        }
+        val => {
+            return Err(eval_err(&format!(
+                "{name} requires a string or a function as replacement, got '{val}'"
+            )))
+        }
+    };
+    Ok(Value::string(&replaced))
+}
+
+/// Folds the sets in `args` with `function`, from left to right
+fn set_operation(
+    name: &str,
+    args: Vec<Value>,
+    function: fn(&Set, &Set) -> Set,
+) -> RuntimeResult<Value> {
+    let (first, rest) = args
+        .split_first()
+        .ok_or_else(|| eval_err(&format!("{name} requires a set as argument, none given")))?;
+    let first = first.expect_set()?.clone();
+    rest.iter()
+        .try_fold(first, |acc, set| Ok(function(&acc, set.expect_set()?)))
+        .map(Value::Set)
 }
 
 #[cfg(test)]
@@ -198,4 +481,19 @@ mod test {
         let args = vec![];
         assert!(Env::new_bindings(None, params, args).is_err());
    }
+
+    #[test]
+    fn testing_command_line_args() {
+        let env = default_environment();
+        let argv = Symbol::from("*ARGV*");
+        assert_eq!(env.borrow().get(&argv), Some(Value::List(List::new())));
+
+        set_command_line_args(&env, &[String::from("a b")]);
+        let args = Value::List(List::new().prepend(Value::string("a b")));
+        assert_eq!(env.borrow().get(&argv), Some(args.clone()));
+        assert_eq!(
+            env.borrow().get(&Symbol::from("*command-line-args*")),
+            Some(args)
+        );
+    }
 }
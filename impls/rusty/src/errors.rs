@@ -11,6 +11,23 @@ pub enum TokenizerError {
     UnbalancedArray,
     UnbalancedList,
     UnbalancedMap,
+    UnbalancedSet,
+    Regex(String),
+}
+
+impl TokenizerError {
+    /// True when the input ended in the middle of a form, i.e. reading more
+    /// input could make it valid
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            TokenizerError::Quote(_)
+                | TokenizerError::UnbalancedList
+                | TokenizerError::UnbalancedArray
+                | TokenizerError::UnbalancedMap
+                | TokenizerError::UnbalancedSet
+        )
+    }
 }
 
 impl std::error::Error for TokenizerError {}
@@ -36,6 +53,8 @@ impl core::fmt::Display for TokenizerError {
             TokenizerError::UnbalancedList => write!(f, "EOF while parsing List"),
             TokenizerError::UnbalancedArray => write!(f, "EOF while parsing Array"),
             TokenizerError::UnbalancedMap => write!(f, "EOF while parsing Map"),
+            TokenizerError::UnbalancedSet => write!(f, "EOF while parsing Set"),
+            TokenizerError::Regex(error) => write!(f, "Tokenizer Error, Regex Error {}", error),
         }
     }
 }
@@ -0,0 +1,202 @@
+use crate::cst::{parse, Node};
+use crate::errors::*;
+
+/// The forms whose body is indented by two columns instead of being aligned
+/// with their first argument
+const BODY_FORMS: [&str; 9] = [
+    "fn*", "let*", "do", "if", "try*", "catch*", "cond", "when", "unless",
+];
+
+/// A form together with the reader macros in front of it, or a comment, and
+/// the number of line breaks that preceded it in the source
+struct Item<'a> {
+    prefixes: Vec<&'a str>,
+    node: &'a Node,
+    breaks: usize,
+}
+
+impl Item<'_> {
+    fn is_comment(&self) -> bool {
+        matches!(self.node, Node::Comment(_))
+    }
+}
+
+fn items(nodes: &[Node]) -> Vec<Item<'_>> {
+    let mut items = Vec::new();
+    let mut prefixes = Vec::new();
+    let mut breaks = 0;
+    for node in nodes {
+        match node {
+            Node::Space(text) => breaks += text.matches('\n').count(),
+            Node::Prefix(text) => prefixes.push(text.as_str()),
+            node => {
+                items.push(Item {
+                    prefixes: std::mem::take(&mut prefixes),
+                    node,
+                    breaks,
+                });
+                breaks = 0;
+            }
+        }
+    }
+    items
+}
+
+struct Formatter {
+    out: String,
+    column: usize,
+}
+
+impl Formatter {
+    fn emit(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.column = match text.rfind('\n') {
+            Some(i) => text[i + 1..].chars().count(),
+            None => self.column + text.chars().count(),
+        };
+    }
+
+    // At most one blank line is kept
+    fn newline(&mut self, blank: bool, indent: usize) {
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+        if blank {
+            self.out.push('\n');
+        }
+        self.out.push_str(&" ".repeat(indent));
+        self.column = indent;
+    }
+
+    /// Lays out a sequence of items: one space between the items written on
+    /// the same line, and the given indentation for those on a new line.
+    /// The indentation may depend on the column of the second item, when it
+    /// is on the same line as the first. Returns the indentation in effect.
+    fn items(&mut self, items: &[Item], indent: &dyn Fn(Option<usize>) -> usize) -> usize {
+        let mut second = None;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                if item.breaks > 0 || items[i - 1].is_comment() {
+                    self.newline(item.breaks > 1, indent(second));
+                } else {
+                    self.emit(" ");
+                    if i == 1 {
+                        second = Some(self.column);
+                    }
+                }
+            }
+            for prefix in &item.prefixes {
+                self.emit(prefix);
+            }
+            self.node(item.node);
+        }
+        indent(second)
+    }
+
+    fn node(&mut self, node: &Node) {
+        let (open, children, close) = match node {
+            Node::Comment(text) => return self.emit(text.trim_end()),
+            Node::Space(text) | Node::Prefix(text) | Node::Atom(text) | Node::String(text) => {
+                return self.emit(text)
+            }
+            Node::List(children) => ("(", children, ")"),
+            Node::Array(children) => ("[", children, "]"),
+            Node::Map(children) => ("{", children, "}"),
+            Node::Set(children) => ("#{", children, "}"),
+        };
+        let start = self.column;
+        self.emit(open);
+        let items = items(children);
+
+        // Calls are aligned with their first argument, when it is on the same
+        // line as the function, and the bodies of special forms are indented
+        let head = match (node, items.first()) {
+            (Node::List(_), Some(item)) if item.prefixes.is_empty() => match item.node {
+                Node::Atom(name) => Some(name.as_str()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let body = head.is_some_and(|name| BODY_FORMS.contains(&name) || name.starts_with("def"));
+        let indent = self.items(&items, &|second| match (head, second) {
+            _ if body => start + 2,
+            (Some(_), Some(column)) => column,
+            _ => start + 1,
+        });
+
+        if items.last().is_some_and(Item::is_comment) {
+            self.newline(false, indent);
+        }
+        self.emit(close);
+    }
+}
+
+/// Reformats mal source code, keeping its comments and blank lines. Each
+/// form keeps the line breaks it was written with, but is re-indented and
+/// has its closing brackets moved to the end of its last line.
+pub fn format(input: &str) -> TokenizerResult<String> {
+    let nodes = parse(input)?;
+    let mut formatter = Formatter {
+        out: String::new(),
+        column: 0,
+    };
+    formatter.items(&items(&nodes), &|_| 0);
+    if !formatter.out.is_empty() {
+        formatter.out.push('\n');
+    }
+    Ok(formatter.out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(input: &str, expected: &str) {
+        let formatted = format(input).expect("We should be able to format the input");
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            format(&formatted).unwrap(),
+            expected,
+            "formatting is stable"
+        );
+    }
+
+    #[test]
+    fn testing_spacing() {
+        check("(  +   1 ,2   )", "(+ 1 2)\n");
+        check("[ 1 2\n]", "[1 2]\n");
+        check("' ( a @ b ~@ c )", "'(a @b ~@c)\n");
+        check("", "");
+        check("\n\n", "");
+    }
+
+    #[test]
+    fn testing_indentation() {
+        check(
+            "(def! f\n(fn* (a b)\n(+ a\nb)))",
+            "(def! f\n  (fn* (a b)\n    (+ a\n       b)))\n",
+        );
+        check(
+            "(let* [a 1\nb 2]\n      a)",
+            "(let* [a 1\n       b 2]\n  a)\n",
+        );
+        check("(\nf\n1\n2\n)", "(f\n 1\n 2)\n");
+        check("{:a 1\n    :b 2}", "{:a 1\n :b 2}\n");
+        check("((f)\n1)", "((f)\n 1)\n");
+    }
+
+    #[test]
+    fn testing_comments_and_blank_lines() {
+        check(
+            ";; header   \n\n\n\n(def! a 1)   ; one\n(def! b ; two\n2\n; last\n)\n\n",
+            ";; header\n\n(def! a 1) ; one\n(def! b ; two\n  2\n  ; last\n  )\n",
+        );
+        check("(do\n\n\n1\n\n2)", "(do\n\n  1\n\n  2)\n");
+    }
+
+    #[test]
+    fn testing_format_errors() {
+        assert_eq!(format("(a"), Err(TokenizerError::UnbalancedList));
+        assert!(format("\"abc").is_err());
+    }
+}
@@ -1,7 +1,10 @@
+pub mod cst;
 pub mod env;
 pub mod errors;
+pub mod format;
 pub mod list;
 pub mod mal;
 mod reader;
+pub mod repl;
 pub mod types;
 mod tests;
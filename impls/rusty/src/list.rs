@@ -11,6 +11,8 @@ type Link<T> = Option<Rc<Node<T>>>;
 struct Node<T> {
     elem: T,
     next: Link<T>,
+    /// The length of the list starting at this node
+    len: usize,
 }
 
 impl<T: Clone> Default for List<T> {
@@ -26,7 +28,7 @@ impl<T: Clone> List<T> {
         List { head: None }
     }
 
-    pub fn empty(&self) -> bool {
+    pub fn is_empty(&self) -> bool {
         self.head.is_none()
     }
 
@@ -35,6 +37,7 @@ impl<T: Clone> List<T> {
             head: Some(Rc::new(Node {
                 elem,
                 next: self.head.clone(),
+                len: self.len() + 1,
             })),
         }
     }
@@ -45,6 +48,10 @@ impl<T: Clone> List<T> {
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.head.as_ref().map_or(0, |node| node.len)
+    }
+
     pub fn head(&self) -> Option<&T> {
         self.head.as_ref().map(|node| &node.elem)
     }
@@ -143,4 +150,13 @@ mod test {
         assert_eq!(iter.next(), Some(&2));
         assert_eq!(iter.next(), Some(&1));
     }
+
+    #[test]
+    fn len() {
+        let list = List::new().prepend(1).prepend(2).prepend(3);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.tail().len(), 2);
+        assert_eq!(list.reverse().len(), 3);
+        assert_eq!(List::<i32>::NIL.len(), 0);
+    }
 }
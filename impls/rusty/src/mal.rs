@@ -161,9 +161,11 @@ fn eval_function(env: &RcEnv, list: List<Value>) -> RuntimeResult<Value> {
     )
 }
 
-fn call_function(env: &RcEnv, func: Value, args: Vec<Value>) -> RuntimeResult<Value> {
+pub fn call_function(env: &RcEnv, func: Value, args: Vec<Value>) -> RuntimeResult<Value> {
     if let Value::NativeFun(native_func) = func {
         return native_func(env.clone(), args);
+    } else if let Value::NativeClosure(closure) = func {
+        return closure.call(env.clone(), args);
     } else if let Value::LispClosure(closure) = func {
         // TODO: is passing the lisp_env enough? probalby we need to prevent shadowing?
         return call_closure(env.clone(), closure, args);
@@ -207,12 +209,22 @@ fn eval_map(env: &RcEnv, list: List<Value>) -> RuntimeResult<Value> {
         .map(Value::Map)
 }
 
+fn eval_set(env: &RcEnv, set: Set) -> RuntimeResult<Value> {
+    // The elements come newest first, add them back oldest first
+    let elements = set
+        .iter()
+        .map(|val| eval(env, val.clone()))
+        .collect::<Result<Vec<Value>, RuntimeError>>()?;
+    Ok(Value::Set(elements.into_iter().rev().collect()))
+}
+
 pub fn eval_ast(env: &RcEnv, ast: Value) -> RuntimeResult<Value> {
     match ast {
         Value::List(ref x) if *x != List::NIL => eval(env, ast),
         Value::Symbol(val) => get_symbol(env, val),
         Value::Array(array) => eval_array(env, array),
         Value::Map(map) => eval_map(env, map),
+        Value::Set(set) => eval_set(env, set),
         _ => Ok(ast.clone()),
     }
 }
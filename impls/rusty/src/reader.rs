@@ -2,6 +2,8 @@ use crate::errors::*;
 use crate::list::*;
 use crate::types::*;
 use regex::Regex;
+use std::ops::Range;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Tokens {
@@ -12,12 +14,15 @@ pub enum Tokens {
     RightSquareBraket,
     LeftBraket,
     RightBraket,
+    HashBraket,
     String(String),
+    /// A regex literal `#"..."`, quotes included
+    Regex(String),
     Comment(String),
     Atom(String),
 } // Captures a sequence of zero or more non special characters (e.g. symbols, numbers, "true", "false")
 
-const STANDALONE_TOKENS_MAPPING: [(&str, Tokens); 7] = [
+const STANDALONE_TOKENS_MAPPING: [(&str, Tokens); 8] = [
     ("~@", Tokens::TildeAt),
     ("(", Tokens::LeftParen),
     (")", Tokens::RightParen),
@@ -25,8 +30,38 @@ const STANDALONE_TOKENS_MAPPING: [(&str, Tokens); 7] = [
     ("]", Tokens::RightSquareBraket),
     ("{", Tokens::LeftBraket),
     ("}", Tokens::RightBraket),
+    ("#{", Tokens::HashBraket),
 ];
 
+const TOKEN_REGEX: &str =
+    r###"[\s,]*(~@|#\{|[\[\]{}()'`~^@]|#?"(?:\\.|[^\\"])*"?|;.*|[^\s\[\]{}('"`,;)]+)"###;
+
+/// Splits `input` into tokens along with the byte range each one covers.
+/// Unlike `Reader::tokenize` this never fails, an unterminated string is
+/// returned as a `Tokens::String` running to the end of the input, which is
+/// what the line editor needs to highlight code while it is being typed.
+pub fn scan(input: &str) -> Vec<(Range<usize>, Tokens)> {
+    // Compiled once, the highlighter scans the line on every keystroke
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = REGEX.get_or_init(|| Regex::new(TOKEN_REGEX).unwrap());
+
+    regex
+        .captures_iter(input)
+        .filter_map(|cap| cap.get(1))
+        .map(|m| {
+            let text = m.as_str();
+            let token = match STANDALONE_TOKENS_MAPPING.iter().find(|&x| x.0 == text) {
+                Some(token_mapping) => token_mapping.1.clone(),
+                None if text.starts_with(';') => Tokens::Comment(text.to_string()),
+                None if text.starts_with('\"') => Tokens::String(text.to_string()),
+                None if text.starts_with("#\"") => Tokens::Regex(text.to_string()),
+                None => Tokens::Atom(text.to_string()),
+            };
+            (m.range(), token)
+        })
+        .collect()
+}
+
 pub trait ReaderTrait {
     fn new(tokens: Vec<Tokens>) -> Self;
     fn index(&mut self) -> usize;
@@ -77,34 +112,19 @@ impl<T: ReaderTrait> Reader<T> {
     // input by hand in order to save the position of the token so the error report
     // can be more useful
     pub fn tokenize(input: &str) -> TokenizerResult<Reader<T>> {
-        let regex = Regex::new(concat!(
-            r###"[\s,]*(~@|[\[\]{}()'`~^@]|"(?:\\.|[^\\"])*"?|;.*|[^\s\[\]{}('"`,;)]+)"###
-        ))
-        .unwrap();
-
         let mut tokens = Vec::new();
 
-        for cap in regex.captures_iter(input) {
-            match STANDALONE_TOKENS_MAPPING.iter().find(|&x| x.0 == &cap[1]) {
-                Some(token_mapping) => tokens.push(token_mapping.1.clone()),
-                None => {
-                    if cap[1].starts_with(';') {
-                        let token = Tokens::Comment(cap[1].to_string());
-                        tokens.push(token);
-                    } else if cap[1].starts_with('\"') {
-                        if !cap[1].ends_with('\"') || cap[1].len() < 2 {
-                            return Err(TokenizerError::Quote(format!(
-                                "unterminated quote starting at {}",
-                                &cap[1]
-                            )));
-                        }
-                        tokens.push(Tokens::String(cap[1].to_string()));
-                    } else {
-                        let token = Tokens::Atom(cap[1].to_string());
-                        tokens.push(token);
-                    }
+        for (_, token) in scan(input) {
+            if let Tokens::String(content) | Tokens::Regex(content) = &token {
+                let quoted = content.strip_prefix('#').unwrap_or(content);
+                if !quoted.ends_with('\"') || quoted.len() < 2 {
+                    return Err(TokenizerError::Quote(format!(
+                        "unterminated quote starting at {}",
+                        content
+                    )));
                 }
             }
+            tokens.push(token);
         }
         let intr = Reader {
             internal_reader: T::new(tokens),
@@ -135,20 +155,28 @@ impl<T: ReaderTrait> Reader<T> {
                 Value::List,
                 TokenizerError::UnbalancedList,
             ),
-            Tokens::RightParen => Err(TokenizerError::UnbalancedList),
+            Tokens::RightParen => Err(TokenizerError::Paren(String::from("unexpected ')'"))),
             Tokens::LeftSquareBraket => self.read_sequence_until(
                 Tokens::RightSquareBraket,
                 Value::Array,
                 TokenizerError::UnbalancedArray,
             ),
-            Tokens::RightSquareBraket => Err(TokenizerError::UnbalancedArray),
+            Tokens::RightSquareBraket => {
+                Err(TokenizerError::Braket(String::from("unexpected ']'")))
+            }
             Tokens::LeftBraket => self.read_sequence_until(
                 Tokens::RightBraket,
                 Value::Map,
                 TokenizerError::UnbalancedMap,
             ),
-            Tokens::RightBraket => Err(TokenizerError::UnbalancedMap),
+            Tokens::RightBraket => Err(TokenizerError::Braket(String::from("unexpected '}'"))),
+            Tokens::HashBraket => self.read_sequence_until(
+                Tokens::RightBraket,
+                |list| Value::Set(list.iter().cloned().collect()),
+                TokenizerError::UnbalancedSet,
+            ),
             Tokens::String(content) => self.validate_string(content),
+            Tokens::Regex(content) => self.read_regex(content),
             Tokens::Comment(_) => self.read_from(), // skip the current comment
             Tokens::Atom(content) => self.read_atom(content),
         }
@@ -209,6 +237,18 @@ impl<T: ReaderTrait> Reader<T> {
         }
     }
 
+    fn read_regex(&mut self, content: String) -> TokenizerResult<Value> {
+        // Only \" is an escape, the other backslashes are part of the pattern
+        let pattern = self.validate_string(content[1..].to_string())?;
+        let pattern = match pattern {
+            Value::String(quoted) => quoted[1..quoted.len() - 1].replace("\\\"", "\""),
+            _ => unreachable!("validate_string always returns a string"),
+        };
+        crate::types::Regex::new(&pattern)
+            .map(Value::Regex)
+            .map_err(|e| TokenizerError::Regex(format!("invalid regex #\"{pattern}\": {e}")))
+    }
+
     fn read_sequence_until<F>(
         &mut self,
         stop_token: Tokens,
@@ -438,4 +478,62 @@ pub mod test {
         let ast = reader.read_from();
         assert!(ast.is_err());
     }
+
+    #[test]
+    fn testing_incomplete_input() {
+        let mut reader = Reader::<InternalReader>::tokenize("(def! f (fn* (x)")
+            .expect("We should be able to create a Reader");
+        assert!(reader.read_from().unwrap_err().is_incomplete());
+
+        let mut reader = Reader::<InternalReader>::tokenize("[1 {:a 2}")
+            .expect("We should be able to create a Reader");
+        assert!(reader.read_from().unwrap_err().is_incomplete());
+
+        let error = Reader::<InternalReader>::tokenize("(str \"abc").unwrap_err();
+        assert!(error.is_incomplete());
+
+        let mut reader = Reader::<InternalReader>::tokenize("(+ 1 2))")
+            .expect("We should be able to create a Reader");
+        assert!(reader.read_from().is_ok());
+        let error = reader.read_from().unwrap_err();
+        assert!(!error.is_incomplete());
+        assert_eq!(error, TokenizerError::Paren(String::from("unexpected ')'")));
+    }
+
+    #[test]
+    fn testing_read_set() {
+        let mut reader = Reader::<InternalReader>::tokenize("#{1 :a 1}")
+            .expect("We should be able to create a Reader");
+        let ast = reader
+            .read_from()
+            .expect("We should be able to parse a set");
+        let set: Set = [Value::Integer(1), Value::Keyword(String::from(":a"))]
+            .into_iter()
+            .collect();
+        assert_eq!(ast, Value::Set(set));
+        assert_eq!(ast.to_string(), "#{1 :a}");
+
+        let mut reader = Reader::<InternalReader>::tokenize("#{1 [2]")
+            .expect("We should be able to create a Reader");
+        assert_eq!(reader.read_from(), Err(TokenizerError::UnbalancedSet));
+    }
+
+    #[test]
+    fn testing_read_regex() {
+        let mut reader = Reader::<InternalReader>::tokenize(r#"#"a\d+\"" "#)
+            .expect("We should be able to create a Reader");
+        let ast = reader
+            .read_from()
+            .expect("We should be able to parse a regex");
+        let regex = crate::types::Regex::new(r#"a\d+""#).unwrap();
+        assert_eq!(ast, Value::Regex(regex));
+        assert_eq!(ast.to_string(), r#"#"a\d+\"""#);
+
+        let error = Reader::<InternalReader>::tokenize(r#"#"a"#).unwrap_err();
+        assert!(error.is_incomplete());
+
+        let mut reader = Reader::<InternalReader>::tokenize(r#"#"(""#)
+            .expect("We should be able to create a Reader");
+        assert!(matches!(reader.read_from(), Err(TokenizerError::Regex(_))));
+    }
 }
@@ -0,0 +1,398 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::BTreeSet,
+    io::{stdout, IsTerminal},
+    ops::Range,
+};
+
+use regex::Regex;
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::{Hint, Hinter},
+    validate::{ValidationContext, ValidationResult, Validator},
+    Context, Helper,
+};
+
+use crate::{
+    env::RcEnv,
+    mal::read,
+    reader::{scan, Tokens},
+    types::{Symbol, Value},
+};
+
+pub const SPECIAL_FORMS: [&str; 5] = ["def!", "let*", "if", "fn*", "do"];
+
+const DELIMITERS: &str = "()[]{}'`~@^\",;";
+
+const STRING_COLOR: &str = "32";
+const KEYWORD_COLOR: &str = "35";
+const CONSTANT_COLOR: &str = "36";
+const COMMENT_COLOR: &str = "90";
+const SPECIAL_FORM_COLOR: &str = "1;34";
+const MATCHING_BRACKET_COLOR: &str = "1;33";
+
+/// A hint that is only displayed, pressing the right arrow doesn't insert it.
+pub struct DisplayHint(String);
+
+impl Hint for DisplayHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Line editor helper completing from the symbols bound in the live
+/// environment, the special forms and the keywords typed so far. It also
+/// colors the input line, unless stdout is not a terminal.
+pub struct MalHelper {
+    env: RcEnv,
+    keywords: RefCell<BTreeSet<String>>,
+    keyword_regex: Regex,
+    color: bool,
+}
+
+impl MalHelper {
+    pub fn new(env: RcEnv) -> Self {
+        MalHelper {
+            env,
+            keywords: RefCell::new(BTreeSet::new()),
+            keyword_regex: Regex::new(r#"(?:^|[\s,(\[{'`~@^])(:[^\s\[\]{}('"`,;)]+)"#).unwrap(),
+            color: stdout().is_terminal(),
+        }
+    }
+
+    /// Force syntax highlighting on or off
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Remember the keywords of an input line so they can be completed later
+    pub fn observe(&self, line: &str) {
+        let mut keywords = self.keywords.borrow_mut();
+        for cap in self.keyword_regex.captures_iter(line) {
+            keywords.insert(cap[1].to_string());
+        }
+    }
+
+    fn symbols(&self) -> BTreeSet<String> {
+        let mut symbols: BTreeSet<String> = self
+            .env
+            .borrow()
+            .symbols()
+            .into_iter()
+            .map(|Symbol(name)| name)
+            .collect();
+        symbols.extend(SPECIAL_FORMS.iter().map(|form| form.to_string()));
+        symbols
+    }
+
+    pub fn candidates(&self, prefix: &str) -> Vec<String> {
+        if prefix.starts_with(':') {
+            self.keywords
+                .borrow()
+                .iter()
+                .filter(|keyword| keyword.starts_with(prefix))
+                .cloned()
+                .collect()
+        } else {
+            self.symbols()
+                .into_iter()
+                .filter(|symbol| symbol.starts_with(prefix))
+                .collect()
+        }
+    }
+
+    /// The call signature "(name param ...)" of a closure bound to `name`
+    pub fn signature(&self, name: &str) -> Option<String> {
+        match self.env.borrow().get(&Symbol::from(name))? {
+            Value::LispClosure(closure) => {
+                let params = closure.params().iter().map(|param| param.to_string());
+                let words: Vec<String> = std::iter::once(name.to_string()).chain(params).collect();
+                Some(format!("({})", words.join(" ")))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// True when `input` stops in the middle of a form and the REPL should keep
+/// reading lines before evaluating it
+pub fn incomplete(input: &str) -> bool {
+    matches!(read(input), Err(error) if error.is_incomplete())
+}
+
+/// The position of the bracket matching the one under the cursor, or the one
+/// right before it when the cursor is at the end of a bracket
+fn matching_bracket(tokens: &[(Range<usize>, Tokens)], pos: usize) -> Option<usize> {
+    let is_bracket = |token: &Tokens| opening(token) || closing(token);
+    let index = tokens
+        .iter()
+        .position(|(range, token)| range.start == pos && is_bracket(token))
+        .or_else(|| {
+            tokens
+                .iter()
+                .position(|(range, token)| range.end == pos && is_bracket(token))
+        })?;
+    let mut depth = 0;
+    if opening(&tokens[index].1) {
+        for (range, token) in &tokens[index..] {
+            if opening(token) {
+                depth += 1;
+            } else if closing(token) {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(range.start);
+                }
+            }
+        }
+    } else {
+        for (range, token) in tokens[..=index].iter().rev() {
+            if closing(token) {
+                depth += 1;
+            } else if opening(token) {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(range.start);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn opening(token: &Tokens) -> bool {
+    matches!(
+        token,
+        Tokens::LeftParen | Tokens::LeftSquareBraket | Tokens::LeftBraket | Tokens::HashBraket
+    )
+}
+
+fn closing(token: &Tokens) -> bool {
+    matches!(
+        token,
+        Tokens::RightParen | Tokens::RightSquareBraket | Tokens::RightBraket
+    )
+}
+
+fn token_color(token: &Tokens) -> Option<&'static str> {
+    match token {
+        Tokens::String(_) | Tokens::Regex(_) => Some(STRING_COLOR),
+        Tokens::Comment(_) => Some(COMMENT_COLOR),
+        Tokens::Atom(atom) if atom.starts_with(':') => Some(KEYWORD_COLOR),
+        Tokens::Atom(atom) if SPECIAL_FORMS.contains(&atom.as_str()) => Some(SPECIAL_FORM_COLOR),
+        Tokens::Atom(atom) if atom.parse::<i64>().is_ok() => Some(CONSTANT_COLOR),
+        Tokens::Atom(atom) if ["nil", "true", "false"].contains(&atom.as_str()) => {
+            Some(CONSTANT_COLOR)
+        }
+        _ => None,
+    }
+}
+
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .find(|(_, ch)| ch.is_whitespace() || DELIMITERS.contains(*ch))
+        .map_or(0, |(index, ch)| index + ch.len_utf8())
+}
+
+/// The symbol at the head of the innermost list still open at `pos`
+fn call_head(line: &str, pos: usize) -> Option<&str> {
+    let mut depth = 0;
+    let mut open = None;
+    for (index, ch) in line[..pos].char_indices().rev() {
+        match ch {
+            ')' | ']' | '}' => depth += 1,
+            '(' | '[' | '{' if depth > 0 => depth -= 1,
+            '(' => {
+                open = Some(index + 1);
+                break;
+            }
+            '[' | '{' => break,
+            _ => (),
+        }
+    }
+    let rest = &line[open?..pos];
+    let end = rest
+        .find(|ch: char| ch.is_whitespace() || DELIMITERS.contains(ch))
+        .unwrap_or(rest.len());
+    Some(&rest[..end]).filter(|head| !head.is_empty())
+}
+
+impl Completer for MalHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = word_start(line, pos);
+        let pairs = self
+            .candidates(&line[start..pos])
+            .into_iter()
+            .map(|name| Pair {
+                display: self.signature(&name).unwrap_or_else(|| name.clone()),
+                replacement: name,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for MalHelper {
+    type Hint = DisplayHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<DisplayHint> {
+        if pos < line.len() {
+            return None;
+        }
+        let prefix = &line[word_start(line, pos)..pos];
+        let (rest, name) = match self.candidates(prefix).as_slice() {
+            [only] if !prefix.is_empty() => (only[prefix.len()..].to_string(), only.clone()),
+            _ => (String::new(), call_head(line, pos)?.to_string()),
+        };
+        match self.signature(&name) {
+            Some(signature) => Some(DisplayHint(format!("{rest}  {signature}"))),
+            None if !rest.is_empty() => Some(DisplayHint(rest)),
+            None => None,
+        }
+    }
+}
+
+impl Highlighter for MalHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if !self.color {
+            return Cow::Borrowed(line);
+        }
+        let tokens = scan(line);
+        let matching = matching_bracket(&tokens, pos);
+        let mut highlighted = String::with_capacity(line.len());
+        let mut last = 0;
+        for (range, token) in &tokens {
+            let color = if Some(range.start) == matching {
+                Some(MATCHING_BRACKET_COLOR)
+            } else {
+                token_color(token)
+            };
+            if let Some(color) = color {
+                highlighted.push_str(&line[last..range.start]);
+                highlighted.push_str(&format!("\x1b[{color}m{}\x1b[0m", &line[range.clone()]));
+                last = range.end;
+            }
+        }
+        highlighted.push_str(&line[last..]);
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if !self.color {
+            return Cow::Borrowed(hint);
+        }
+        Cow::Owned(format!("\x1b[2m{hint}\x1b[0m"))
+    }
+
+    fn highlight_char(&self, line: &str, _pos: usize) -> bool {
+        // The matching bracket moves with the cursor, redraw on every move
+        self.color && !line.is_empty()
+    }
+}
+
+impl Validator for MalHelper {
+    /// Enter inside an unfinished form starts a new line instead of
+    /// submitting the input
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for MalHelper {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::env::default_environment;
+    use crate::mal::*;
+
+    #[test]
+    fn testing_symbol_completion() {
+        let env = default_environment();
+        let helper = MalHelper::new(env.clone());
+        eval(&env, read("(def! my-func (fn* (a b) a))").unwrap()).unwrap();
+
+        assert_eq!(helper.candidates("my-"), vec![String::from("my-func")]);
+        assert!(helper.candidates("l").contains(&String::from("let*")));
+        assert!(helper.candidates("l").contains(&String::from("list")));
+        assert_eq!(
+            helper.signature("my-func"),
+            Some(String::from("(my-func a b)"))
+        );
+        assert_eq!(helper.signature("list"), None);
+    }
+
+    #[test]
+    fn testing_keyword_completion() {
+        let helper = MalHelper::new(default_environment());
+        assert!(helper.candidates(":").is_empty());
+
+        helper.observe("(def! m {:alpha 1 :beta [:alpine]})");
+        assert_eq!(
+            helper.candidates(":al"),
+            vec![String::from(":alpha"), String::from(":alpine")]
+        );
+    }
+
+    #[test]
+    fn testing_incomplete() {
+        assert!(incomplete("(def! f (fn* (x)"));
+        assert!(incomplete("(def! f (fn* (x)\n  (* x 2)"));
+        assert!(incomplete("(str \"abc"));
+        assert!(!incomplete("(def! f (fn* (x)\n  (* x 2)))"));
+        assert!(!incomplete(")"));
+        assert!(!incomplete(""));
+    }
+
+    #[test]
+    fn testing_highlight() {
+        let helper = MalHelper::new(default_environment()).with_color(true);
+        assert_eq!(
+            helper.highlight("(if nil :a \"b\") ; c", 0),
+            "(\x1b[1;34mif\x1b[0m \x1b[36mnil\x1b[0m \x1b[35m:a\x1b[0m \x1b[32m\"b\"\x1b[0m\x1b[1;33m)\x1b[0m \x1b[90m; c\x1b[0m"
+        );
+        assert_eq!(helper.highlight("(+ 1 x", 6), "(+ \x1b[36m1\x1b[0m x");
+
+        let plain = MalHelper::new(default_environment()).with_color(false);
+        assert_eq!(plain.highlight("(if nil 1)", 0), "(if nil 1)");
+    }
+
+    #[test]
+    fn testing_matching_bracket() {
+        let tokens = scan("(a [b \")\"] (c))");
+        assert_eq!(matching_bracket(&tokens, 0), Some(14));
+        assert_eq!(matching_bracket(&tokens, 15), Some(0));
+        assert_eq!(matching_bracket(&tokens, 3), Some(9));
+        assert_eq!(matching_bracket(&tokens, 10), Some(3));
+        assert_eq!(matching_bracket(&tokens, 11), Some(13));
+        assert_eq!(matching_bracket(&tokens, 2), None);
+        assert_eq!(matching_bracket(&scan("(a (b)"), 0), None);
+    }
+
+    #[test]
+    fn testing_call_head() {
+        assert_eq!(call_head("(my-func 1 ", 11), Some("my-func"));
+        assert_eq!(call_head("(my-func (list 1) ", 18), Some("my-func"));
+        assert_eq!(call_head("(my-func [1 ", 11), None);
+        assert_eq!(call_head("1 2", 3), None);
+    }
+}
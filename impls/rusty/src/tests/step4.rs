@@ -232,4 +232,98 @@ mod test {
         println!("Parsed {parsed}");
         assert_eq!(eval(&env, read(expr).unwrap()).unwrap(), Value::Integer(12));
     }
+
+    #[test]
+    fn native_closure_tests() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let env = default_environment();
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let closure = NativeClosure::new("next-id", move |_, args| {
+            counter.set(counter.get() + 1);
+            Ok(Value::Integer(counter.get() * 10 + args.len() as IntType))
+        });
+        env.borrow_mut()
+            .add(Symbol::from("next-id"), Value::NativeClosure(closure.clone()));
+
+        let expr = "(next-id)";
+        assert_eq!(eval(&env, read(expr).unwrap()).unwrap(), Value::Integer(10));
+
+        let expr = "(let* (f next-id) (f 1 2))";
+        assert_eq!(eval(&env, read(expr).unwrap()).unwrap(), Value::Integer(22));
+        assert_eq!(calls.get(), 2);
+
+        let expr = "next-id";
+        let value = eval(&env, read(expr).unwrap()).unwrap();
+        assert_eq!(value.to_string(), "<nativefunc> next-id");
+        assert_eq!(value, Value::NativeClosure(closure));
+        assert_ne!(
+            value,
+            Value::NativeClosure(NativeClosure::new("next-id", |_, _| Ok(Value::Nil)))
+        );
+    }
+
+    #[test]
+    fn set_tests() {
+        let env = default_environment();
+        let run = |expr: &str| eval(&env, read(expr).unwrap()).unwrap();
+
+        assert_eq!(run("#{1 (+ 1 1) 2}").to_string(), "#{1 2}");
+        assert_eq!(run("(= #{1 2 3} (set [3 2 1 2]))"), Value::True);
+        assert_eq!(run("(= #{1 2} #{1 2 3})"), Value::False);
+        assert_eq!(run("(set? #{})"), Value::True);
+        assert_eq!(run("(set? (list))"), Value::False);
+        assert_eq!(run("(count (conj #{1} 1 2))"), Value::Integer(2));
+        assert_eq!(run("(disj #{1 2 3} 2 4)").to_string(), "#{1 3}");
+        assert_eq!(run("(contains? #{:a [1]} [1])"), Value::True);
+        assert_eq!(run("(contains? {:a 1} 1)"), Value::False);
+        assert_eq!(run("(union #{1 2} #{2 3} #{4})").to_string(), "#{1 2 3 4}");
+        assert_eq!(
+            run("(intersection #{1 2 3} #{2 3 4})").to_string(),
+            "#{2 3}"
+        );
+        assert_eq!(run("(difference #{1 2 3} #{2})").to_string(), "#{1 3}");
+        assert_eq!(run("(conj [1] 2)").to_string(), "[1 2]");
+        assert_eq!(run("(empty? (difference #{1} #{1}))"), Value::True);
+
+        let expr = "(union #{1} [2])";
+        assert!(eval(&env, read(expr).unwrap()).is_err());
+    }
+
+    #[test]
+    fn regex_tests() {
+        let env = default_environment();
+        let run = |expr: &str| eval(&env, read(expr).unwrap()).unwrap().to_string();
+
+        assert_eq!(run(r#"(re-pattern "a+")"#), r#"#"a+""#);
+        assert_eq!(run(r#"(re-find #"\d+" "ab 12 34")"#), r#""12""#);
+        assert_eq!(
+            run(r#"(re-find #"(\w+)@(\w+)?" "me@ x")"#),
+            r#"["me@" "me" nil]"#
+        );
+        assert_eq!(run(r#"(re-find #"z" "abc")"#), "nil");
+        assert_eq!(run(r#"(re-matches #"a|ab" "ab")"#), r#""ab""#);
+        assert_eq!(run(r#"(re-matches #"\d" "12")"#), "nil");
+        assert_eq!(
+            run(r#"(re-seq #"(\d)\w" "1a 2b")"#),
+            r#"(["1a" "1"] ["2b" "2"])"#
+        );
+        assert_eq!(run(r#"(replace "a.a.a" "." "$")"#), r#""a$a.a""#);
+        assert_eq!(
+            run(r#"(replace-all "2021-03-04" #"(\d+)-(\d+)-(\d+)" "$3/$2/$1")"#),
+            r#""04/03/2021""#
+        );
+        assert_eq!(
+            run(r#"(replace-all "a1b22" #"\d+" (fn* (m) (count (list m m))))"#),
+            r#""a2b2""#
+        );
+        assert_eq!(run(r#"(= #"a" (re-pattern "a"))"#), "true");
+
+        let expr = r#"(re-find "a" "a")"#;
+        assert!(eval(&env, read(expr).unwrap()).is_err());
+        let expr = r#"(re-pattern "(")"#;
+        assert!(eval(&env, read(expr).unwrap()).is_err());
+    }
 }
@@ -1,5 +1,5 @@
 use crate::{errors::eval_err, list::*};
-use std::{cmp::Ordering, ops::*};
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, fmt, ops::*, rc::Rc};
 
 use crate::{env::RcEnv, errors::RuntimeError, errors::RuntimeResult};
 
@@ -39,6 +39,49 @@ impl LispClosure {
 
 }
 
+/// A native function backed by a closure, which unlike a `NativeFun` can
+/// capture host state such as a handle or a counter
+#[derive(Clone)]
+pub struct NativeClosure {
+    name: String,
+    func: Rc<dyn Fn(RcEnv, Vec<Value>) -> RuntimeResult<Value>>,
+}
+
+impl NativeClosure {
+    pub fn new<F>(name: &str, func: F) -> Self
+    where
+        F: Fn(RcEnv, Vec<Value>) -> RuntimeResult<Value> + 'static,
+    {
+        Self {
+            name: String::from(name),
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn call(&self, env: RcEnv, args: Vec<Value>) -> RuntimeResult<Value> {
+        (self.func)(env, args)
+    }
+}
+
+impl fmt::Debug for NativeClosure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeClosure")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// Two closures are equal only when they are the same closure
+impl PartialEq for NativeClosure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(Rc::as_ptr(&self.func), Rc::as_ptr(&other.func))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Symbol(pub String);
 impl From<&str> for Symbol {
@@ -53,6 +96,133 @@ impl std::fmt::Display for Symbol {
     }
 }
 
+/// A persistent set of values. Adding or removing an element gives back a
+/// new set sharing its tail with the old one, the newest element first.
+/// Two sets are equal when they hold the same elements, in any order.
+///
+/// The elements are kept in a list that is searched linearly, so looking
+/// one up or adding one takes time proportional to the size of the set.
+#[derive(Clone, Debug, Default)]
+pub struct Set(List<Value>);
+
+impl Set {
+    pub fn contains(&self, value: &Value) -> bool {
+        self.0.iter().any(|elem| elem == value)
+    }
+
+    pub fn insert(&self, value: Value) -> Set {
+        if self.contains(&value) {
+            self.clone()
+        } else {
+            Set(self.0.prepend(value))
+        }
+    }
+
+    pub fn remove(&self, value: &Value) -> Set {
+        if self.contains(value) {
+            Set(self.iter().filter(|elem| *elem != value).cloned().collect())
+        } else {
+            self.clone()
+        }
+    }
+
+    pub fn union(&self, other: &Set) -> Set {
+        other
+            .0
+            .reverse()
+            .iter()
+            .fold(self.clone(), |set, elem| set.insert(elem.clone()))
+    }
+
+    pub fn intersection(&self, other: &Set) -> Set {
+        Set(self
+            .iter()
+            .filter(|elem| other.contains(elem))
+            .cloned()
+            .collect())
+    }
+
+    pub fn difference(&self, other: &Set) -> Set {
+        Set(self
+            .iter()
+            .filter(|elem| !other.contains(elem))
+            .cloned()
+            .collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The elements, newest first
+    pub fn iter(&self) -> Iter<'_, Value> {
+        self.0.iter()
+    }
+}
+
+impl PartialEq for Set {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|elem| other.contains(elem))
+    }
+}
+
+impl FromIterator<Value> for Set {
+    fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
+        iter.into_iter()
+            .fold(Set::default(), |set, elem| set.insert(elem))
+    }
+}
+
+/// A compiled regular expression, read from a `#"..."` literal or made by
+/// `re-pattern`. Two regexes are equal when their patterns are.
+#[derive(Clone, Debug)]
+pub struct Regex(regex::Regex);
+
+/// The most patterns kept compiled, so that patterns built at runtime can't
+/// use up memory
+const MAX_PATTERNS: usize = 256;
+
+thread_local! {
+    static PATTERNS: RefCell<HashMap<String, regex::Regex>> = RefCell::new(HashMap::new());
+}
+
+impl Regex {
+    /// Compiles `pattern`, or reuses the regex compiled the last time it
+    /// was seen, since compiling is much slower than matching
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        PATTERNS.with(|patterns| {
+            let mut patterns = patterns.borrow_mut();
+            if let Some(regex) = patterns.get(pattern) {
+                return Ok(Regex(regex.clone()));
+            }
+            let regex = regex::Regex::new(pattern)?;
+            if patterns.len() >= MAX_PATTERNS {
+                patterns.clear();
+            }
+            patterns.insert(pattern.to_string(), regex.clone());
+            Ok(Regex(regex))
+        })
+    }
+
+    pub fn as_regex(&self) -> &regex::Regex {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Integer(IntType),
@@ -71,7 +241,10 @@ pub enum Value {
     Array(List<Value>),
     List(List<Value>),
     Map(List<Value>),
+    Set(Set),
+    Regex(Regex),
     NativeFun(NativeFun),
+    NativeClosure(NativeClosure),
     LispClosure(LispClosure),
 }
 
@@ -94,6 +267,61 @@ impl Value {
         }
     }
 
+    pub fn expect_set(&self) -> RuntimeResult<&Set> {
+        match self {
+            Value::Set(set) => Ok(set),
+            val => Err(RuntimeError::Evaluation(format!(
+                "Value '{val}' is not a set"
+            ))),
+        }
+    }
+
+    pub fn expect_regex(&self) -> RuntimeResult<&Regex> {
+        match self {
+            Value::Regex(regex) => Ok(regex),
+            val => Err(RuntimeError::Evaluation(format!(
+                "Value '{val}' is not a regex"
+            ))),
+        }
+    }
+
+    /// The text of a string, without the quotes and escapes the reader
+    /// keeps in it
+    pub fn expect_string(&self) -> RuntimeResult<String> {
+        match self {
+            Value::String(content) => {
+                let content = content.strip_prefix('"').unwrap_or(content);
+                let content = content.strip_suffix('"').unwrap_or(content);
+                let mut text = String::new();
+                let mut chars = content.chars();
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some(escaped) => text.push(escaped),
+                            None => text.push(ch),
+                        },
+                        _ => text.push(ch),
+                    }
+                }
+                Ok(text)
+            }
+            val => Err(RuntimeError::Evaluation(format!(
+                "Value '{val}' is not a string"
+            ))),
+        }
+    }
+
+    /// A string holding `text`, quoted and escaped the way the reader keeps
+    /// strings
+    pub fn string(text: &str) -> Value {
+        let escaped = text
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        Value::String(format!("\"{escaped}\""))
+    }
+
     pub fn expect_list_arr(&self) -> RuntimeResult<&List<Value>> {
         match self {
             Value::List(list) => Ok(list),
@@ -124,7 +352,10 @@ impl std::fmt::Display for Value {
             Value::Array(array) => write!(f, "{}", print_seq(array, "[", "]")),
             Value::List(list) => write!(f, "{}", print_seq(list, "(", ")")),
             Value::Map(map) => write!(f, "{}", print_seq(map, "{", "}")),
+            Value::Set(Set(set)) => write!(f, "{}", print_seq(&set.reverse(), "#{", "}")),
+            Value::Regex(regex) => write!(f, "#\"{}\"", regex.as_str().replace('"', "\\\"")),
             Value::NativeFun(func) => write!(f, "<nativefunc> {:?}", func),
+            Value::NativeClosure(closure) => write!(f, "<nativefunc> {}", closure.name()),
             Value::LispClosure(LispClosure{name, env: _, params: _, body}) => write!(f, "<{:?}:: {:?}>", name, body),
         }
     }
@@ -321,6 +552,7 @@ pub fn compatible_types(first: &Value, next: &Value) -> RuntimeResult<()> {
         (Value::Nil, _) => Ok(()),
         (Value::List(_), Value::List(_)) => Ok(()),
         (Value::Map(_), Value::Map(_)) => Ok(()),
+        (Value::Set(_), Value::Set(_)) => Ok(()),
         (Value::Array(_), Value::Array(_)) => Ok(()),
         (Value::Keyword(_), Value::Keyword(_)) => Ok(()),
         (a, b) => Err(eval_err(&format!(